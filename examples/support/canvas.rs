@@ -1,33 +1,59 @@
+use super::time::{Clock, Time};
+use cgpp2::color::*;
+use cgpp2::texture::*;
+use cgpp2::types::*;
 use sdl2_sys::*;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::ptr::{null, null_mut};
 
 struct ReadonlyCanvas {
     width: i32,
     height: i32,
+    renderer: *mut SDL_Renderer,
     texture: *mut SDL_Texture,
+    // Copy of the texture's pixels, locked textures are write-only so reads come from here
+    shadow: Vec<u8>,
+    dither: bool,
+    stats_overlay: bool,
 }
 
 impl ReadonlyCanvas {
-    pub fn new(width: i32, height: i32, texture: *mut SDL_Texture) -> ReadonlyCanvas {
+    pub fn new(width: i32, height: i32, renderer: *mut SDL_Renderer) -> ReadonlyCanvas {
         ReadonlyCanvas {
             width,
             height,
-            texture,
+            renderer,
+            texture: unsafe { create_texture(renderer, width, height) },
+            shadow: vec![0; (width * height * 4) as usize],
+            dither: false,
+            stats_overlay: false,
         }
     }
 
-    pub fn lock(&mut self) -> Canvas {
+    // The streaming texture has a fixed size, so it's replaced
+    pub fn resize(&mut self, width: i32, height: i32) {
+        unsafe {
+            SDL_DestroyTexture(self.texture);
+            self.texture = create_texture(self.renderer, width, height);
+        }
+        self.shadow = vec![0; (width * height * 4) as usize];
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn lock(&mut self) -> Canvas<'_> {
         let mut pixels = null_mut();
         let mut pitch = 0;
         unsafe {
             SDL_LockTexture(self.texture, null_mut(), &mut pixels, &mut pitch);
         }
-        Canvas {
+        let mut canvas = Canvas {
             data: self,
             pixels: pixels as *mut u8,
             pitch,
-        }
+        };
+        canvas.flush();
+        canvas
     }
 }
 
@@ -39,20 +65,125 @@ pub struct Canvas<'a> {
 
 impl<'a> Canvas<'a> {
     pub fn clear(&mut self) {
-        unsafe {
-            std::ptr::write_bytes(self.pixels, 0, (self.width() * self.height() * 4) as usize);
-        }
+        self.data.shadow.iter_mut().for_each(|b| *b = 0);
+        self.flush();
     }
 
+    // Colors are in linear space and get sRGB encoded when written, see `set_dither`
     pub fn set_pixel(&mut self, x: i32, y: i32, r: f32, g: f32, b: f32, a: f32) {
         debug_assert!(x >= 0 && x < self.width());
         debug_assert!(y >= 0 && y < self.height());
+        let dither = if self.data.dither {
+            bayer4x4(x, y)
+        } else {
+            0.0
+        };
+        // Alpha isn't displayed, dithering it would only add noise when it's read back
+        let bytes = [
+            quantize8(a, 0.0),
+            quantize8(linear_to_srgb(b), dither),
+            quantize8(linear_to_srgb(g), dither),
+            quantize8(linear_to_srgb(r), dither),
+        ];
+        let i = ((y * self.width() + x) * 4) as usize;
+        self.data.shadow[i..i + 4].copy_from_slice(&bytes);
         unsafe {
             let pixel = self.pixels.offset((self.pitch * y + x * 4) as isize);
-            *pixel.offset(0) = (((a * 255.0).round() as i32) & 0xFF) as u8;
-            *pixel.offset(1) = (((b * 255.0).round() as i32) & 0xFF) as u8;
-            *pixel.offset(2) = (((g * 255.0).round() as i32) & 0xFF) as u8;
-            *pixel.offset(3) = (((r * 255.0).round() as i32) & 0xFF) as u8;
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), pixel, 4);
+        }
+    }
+
+    // Returns the linear color of the pixel
+    pub fn get_pixel(&self, x: i32, y: i32) -> Vec4 {
+        debug_assert!(x >= 0 && x < self.width());
+        debug_assert!(y >= 0 && y < self.height());
+        let i = ((y * self.width() + x) * 4) as usize;
+        let pixel = &self.data.shadow[i..i + 4];
+        srgb_to_linear_vec4(Vec4::new(
+            pixel[3] as f32 / 255.0,
+            pixel[2] as f32 / 255.0,
+            pixel[1] as f32 / 255.0,
+            pixel[0] as f32 / 255.0,
+        ))
+    }
+
+    // Copies the base level of a texture with the same size as the canvas, textures
+    // start from the bottom row so rows are flipped
+    pub fn draw_texture(&mut self, texture: &Texture) {
+        debug_assert!(texture.width() == self.width() && texture.height() == self.height());
+        let height = self.height();
+        for y in 0..height {
+            for x in 0..self.width() {
+                let c = texture.get_texel(x, y);
+                self.set_pixel(x, height - 1 - y, c.e[0], c.e[1], c.e[2], c.e[3]);
+            }
+        }
+    }
+
+    // Applies ordered dithering when quantizing to 8 bits, hides banding in dark
+    // gradients
+    pub fn set_dither(&mut self, dither: bool) {
+        self.data.dither = dither;
+    }
+
+    // Draws FPS and frame time into the top-left corner after each frame
+    pub fn set_stats_overlay(&mut self, enabled: bool) {
+        self.data.stats_overlay = enabled;
+    }
+
+    // Draws `text` with its top-left corner at (x, y) in a 3x5 pixel font scaled by `scale`.
+    // Only digits, a few upper case letters and punctuation are supported, other characters
+    // are left blank.
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, scale: i32, color: Vec4) {
+        for (i, c) in text.chars().enumerate() {
+            let glyph = glyph(c);
+            let gx = x + i as i32 * 4 * scale;
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (0b100 >> col) == 0 {
+                        continue;
+                    }
+                    for sy in 0..scale {
+                        for sx in 0..scale {
+                            let px = gx + col * scale + sx;
+                            let py = y + row as i32 * scale + sy;
+                            if px >= 0 && px < self.width() && py >= 0 && py < self.height() {
+                                self.set_pixel(
+                                    px, py, color.e[0], color.e[1], color.e[2], color.e[3],
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn draw_stats(&mut self, time: &Time) {
+        let text = format!("{:.0} FPS {:.1} MS", time.fps, time.frame_time);
+        let scale = 2;
+        let width = (text.len() as i32 * 4 + 1) * scale;
+        let height = 7 * scale;
+        for y in 0..height.min(self.height()) {
+            for x in 0..width.min(self.width()) {
+                self.set_pixel(x, y, 0.0, 0.0, 0.0, 1.0);
+            }
+        }
+        self.draw_text(scale, scale, &text, scale, Vec4::new(1.0, 1.0, 1.0, 1.0));
+    }
+
+    // Copies the shadow buffer to the texture, the texture's memory is undefined after
+    // locking so this is done on every lock
+    fn flush(&mut self) {
+        let row = (self.width() * 4) as usize;
+        for y in 0..self.height() as usize {
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    self.data.shadow[y * row..].as_ptr(),
+                    self.pixels.add(y * self.pitch as usize),
+                    row,
+                );
+            }
         }
     }
 
@@ -73,34 +204,286 @@ impl<'a> Drop for Canvas<'a> {
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+impl MouseButton {
+    fn from_sdl(button: u8) -> Option<MouseButton> {
+        match button as u32 {
+            SDL_BUTTON_LEFT => Some(MouseButton::Left),
+            SDL_BUTTON_MIDDLE => Some(MouseButton::Middle),
+            SDL_BUTTON_RIGHT => Some(MouseButton::Right),
+            // `sdl2_sys` exports an X11 constant named `None`
+            _ => Option::None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum WindowEvent {
+    FocusGained,
+    FocusLost,
+    // New size of the window in screen coordinates, which can differ from pixels on high
+    // DPI displays
+    Resized { width: i32, height: i32 },
+}
+
+// State of the input devices for the current frame. "Pressed" and "released" are only true
+// in the frame the change happened, key repeats don't count as presses.
 pub struct Input {
     pub mouse: Mouse,
+    // Text typed since the last frame with the keyboard layout and input method applied,
+    // unlike the scancodes of the key functions
+    pub text: String,
+    pub window_events: Vec<WindowEvent>,
+    pub focused: bool,
+    // Size of the window in screen coordinates, the unit of the mouse position
+    pub window_width: i32,
+    pub window_height: i32,
+    keys_held: Vec<bool>,
+    keys_pressed: Vec<bool>,
+    keys_released: Vec<bool>,
+}
+
+impl Input {
+    fn new(window_width: i32, window_height: i32) -> Input {
+        let num_keys = SDL_Scancode::SDL_NUM_SCANCODES as usize;
+        Input {
+            mouse: Mouse {
+                x: 0,
+                y: 0,
+                dx: 0,
+                dy: 0,
+                wheel_x: 0,
+                wheel_y: 0,
+                held: [false; 3],
+                pressed: [false; 3],
+                released: [false; 3],
+            },
+            text: String::new(),
+            window_events: Vec::new(),
+            focused: true,
+            window_width,
+            window_height,
+            keys_held: vec![false; num_keys],
+            keys_pressed: vec![false; num_keys],
+            keys_released: vec![false; num_keys],
+        }
+    }
+
+    pub fn is_key_held(&self, key: SDL_Scancode) -> bool {
+        self.keys_held[key as usize]
+    }
+
+    pub fn is_key_pressed(&self, key: SDL_Scancode) -> bool {
+        self.keys_pressed[key as usize]
+    }
+
+    pub fn is_key_released(&self, key: SDL_Scancode) -> bool {
+        self.keys_released[key as usize]
+    }
+
+    // Clears everything which only lasts for a frame
+    fn begin_frame(&mut self) {
+        self.keys_pressed.iter_mut().for_each(|k| *k = false);
+        self.keys_released.iter_mut().for_each(|k| *k = false);
+        self.text.clear();
+        self.window_events.clear();
+        let mouse = &mut self.mouse;
+        mouse.dx = 0;
+        mouse.dy = 0;
+        mouse.wheel_x = 0;
+        mouse.wheel_y = 0;
+        mouse.pressed = [false; 3];
+        mouse.released = [false; 3];
+    }
+
+    unsafe fn handle_event(&mut self, event: &SDL_Event) {
+        // Compared as integers, SDL may send event types the bindings don't know about
+        let is = |t: SDL_EventType| event.type_ == t as u32;
+        if is(SDL_EventType::SDL_KEYDOWN) || is(SDL_EventType::SDL_KEYUP) {
+            let key = event.key.keysym.scancode as usize;
+            if key >= self.keys_held.len() {
+                return;
+            }
+            let down = is(SDL_EventType::SDL_KEYDOWN);
+            if down && event.key.repeat == 0 {
+                self.keys_pressed[key] = true;
+            } else if !down {
+                self.keys_released[key] = true;
+            }
+            self.keys_held[key] = down;
+        } else if is(SDL_EventType::SDL_TEXTINPUT) {
+            let text = CStr::from_ptr(event.text.text.as_ptr());
+            self.text.push_str(&text.to_string_lossy());
+        } else if is(SDL_EventType::SDL_MOUSEMOTION) {
+            let motion = event.motion;
+            self.mouse.x = motion.x;
+            self.mouse.y = motion.y;
+            self.mouse.dx += motion.xrel;
+            self.mouse.dy += motion.yrel;
+        } else if is(SDL_EventType::SDL_MOUSEBUTTONDOWN) || is(SDL_EventType::SDL_MOUSEBUTTONUP) {
+            if let Some(button) = MouseButton::from_sdl(event.button.button) {
+                let i = button as usize;
+                let down = is(SDL_EventType::SDL_MOUSEBUTTONDOWN);
+                if down {
+                    self.mouse.pressed[i] = true;
+                } else {
+                    self.mouse.released[i] = true;
+                }
+                self.mouse.held[i] = down;
+            }
+        } else if is(SDL_EventType::SDL_MOUSEWHEEL) {
+            let wheel = event.wheel;
+            let sign = if wheel.direction == SDL_MouseWheelDirection::SDL_MOUSEWHEEL_FLIPPED as u32
+            {
+                -1
+            } else {
+                1
+            };
+            self.mouse.wheel_x += sign * wheel.x;
+            self.mouse.wheel_y += sign * wheel.y;
+        } else if is(SDL_EventType::SDL_WINDOWEVENT) {
+            let window = event.window;
+            let id = window.event as u32;
+            if id == SDL_WindowEventID::SDL_WINDOWEVENT_FOCUS_GAINED as u32 {
+                self.focused = true;
+                self.window_events.push(WindowEvent::FocusGained);
+            } else if id == SDL_WindowEventID::SDL_WINDOWEVENT_FOCUS_LOST as u32 {
+                self.focused = false;
+                // Releases aren't delivered to unfocused windows
+                self.keys_held.iter_mut().for_each(|k| *k = false);
+                self.mouse.held = [false; 3];
+                self.window_events.push(WindowEvent::FocusLost);
+            } else if id == SDL_WindowEventID::SDL_WINDOWEVENT_RESIZED as u32 {
+                self.window_width = window.data1;
+                self.window_height = window.data2;
+                self.window_events.push(WindowEvent::Resized {
+                    width: window.data1,
+                    height: window.data2,
+                });
+            }
+        }
+    }
 }
 
 pub struct Mouse {
+    // Position in window coordinates, y points down
     pub x: i32,
     pub y: i32,
+    // Relative motion since the last frame
+    pub dx: i32,
+    pub dy: i32,
+    // Scrolled amount since the last frame, positive y is away from the user
+    pub wheel_x: i32,
+    pub wheel_y: i32,
+    held: [bool; 3],
+    pressed: [bool; 3],
+    released: [bool; 3],
 }
 
+impl Mouse {
+    pub fn is_held(&self, button: MouseButton) -> bool {
+        self.held[button as usize]
+    }
+
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        self.pressed[button as usize]
+    }
+
+    pub fn is_released(&self, button: MouseButton) -> bool {
+        self.released[button as usize]
+    }
+}
+
+// Rows of a 3x5 glyph, the most significant of the 3 bits is the left column
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' | 'S' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'P' => [0b111, 0b101, 0b111, 0b100, 0b100],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
+
+// `width` and `height` are the initial size of the window. The window can be resized, the
+// canvas passed to `callback` always has the current size and `Input::window_events`
+// reports the resize in the frame it happened.
+//
+// `callback` is called once per frame, with VSYNC the frame rate is the display's refresh
+// rate, so animations should be advanced by `Time::delta`.
 pub fn setup<F>(width: i32, height: i32, callback: F)
 where
-    F: FnMut(&Input, &mut Canvas),
+    F: FnMut(&Input, &Time, &mut Canvas),
+{
+    unsafe {
+        run(width, height, 0.0, |_, _| {}, callback);
+    }
+}
+
+// Like `setup`, but also calls `update` every `fixed_delta` seconds, which may be several
+// times or not at all in a frame, before `callback`. Simulations stay deterministic and
+// independent of the frame rate, `Time::alpha` allows interpolating between updates.
+//
+// The input is the one of the frame, so a key press is seen by every update of the frame,
+// or by none if there is no update.
+pub fn setup_with_fixed_update<U, F>(
+    width: i32,
+    height: i32,
+    fixed_delta: f32,
+    update: U,
+    callback: F,
+) where
+    U: FnMut(&Input, f32),
+    F: FnMut(&Input, &Time, &mut Canvas),
 {
+    debug_assert!(fixed_delta > 0.0);
     unsafe {
-        run(width, height, callback);
+        run(width, height, fixed_delta, update, callback);
     }
 }
 
 macro_rules! sdl_error {
     () => {{
-        use std::ffi::CStr;
         CStr::from_ptr(SDL_GetError()).to_string_lossy()
     }};
 }
 
-unsafe fn run<F>(width: i32, height: i32, mut callback: F)
+unsafe fn create_texture(renderer: *mut SDL_Renderer, width: i32, height: i32) -> *mut SDL_Texture {
+    let texture = SDL_CreateTexture(
+        renderer,
+        SDL_PIXELFORMAT_RGBA8888 as u32,
+        SDL_TextureAccess::SDL_TEXTUREACCESS_STREAMING as i32,
+        width,
+        height,
+    );
+    if texture.is_null() {
+        panic!("Failed to create SDL texture {}", sdl_error!());
+    }
+    texture
+}
+
+unsafe fn run<U, F>(width: i32, height: i32, fixed_delta: f32, mut update: U, mut callback: F)
 where
-    F: FnMut(&Input, &mut Canvas),
+    U: FnMut(&Input, f32),
+    F: FnMut(&Input, &Time, &mut Canvas),
 {
     if SDL_Init(SDL_INIT_VIDEO) != 0 {
         panic!("Failed to init SDL {}", sdl_error!());
@@ -113,7 +496,7 @@ where
         SDL_WINDOWPOS_UNDEFINED_MASK as i32,
         width,
         height,
-        SDL_WindowFlags::SDL_WINDOW_SHOWN as u32,
+        SDL_WindowFlags::SDL_WINDOW_SHOWN as u32 | SDL_WindowFlags::SDL_WINDOW_RESIZABLE as u32,
     );
     if window.is_null() {
         panic!("Failed to create SDL window {}", sdl_error!());
@@ -129,45 +512,58 @@ where
         panic!("Failed to create SDL renderer {}", sdl_error!());
     }
 
-    let texture = SDL_CreateTexture(
-        renderer,
-        SDL_PIXELFORMAT_RGBA8888 as u32,
-        SDL_TextureAccess::SDL_TEXTUREACCESS_STREAMING as i32,
-        width,
-        height,
-    );
-    if texture.is_null() {
-        panic!("Failed to create SDL texture {}", sdl_error!());
-    }
+    let mut readonly_canvas = ReadonlyCanvas::new(width, height, renderer);
 
-    let mut readonly_canvas = ReadonlyCanvas::new(width, height, texture);
+    let mut input = Input::new(width, height);
+    SDL_StartTextInput();
 
-    let mut input = Input {
-        mouse: Mouse { x: 0, y: 0 },
-    };
+    let mut clock = Clock::new(fixed_delta);
 
     'game: loop {
+        input.begin_frame();
         let mut event = std::mem::MaybeUninit::uninit();
         while SDL_PollEvent(event.as_mut_ptr()) != 0 {
             let event = event.assume_init();
-            let event_type: SDL_EventType = std::mem::transmute(event.type_);
-            match event_type {
-                SDL_EventType::SDL_QUIT => break 'game,
-                _ => {}
+            if event.type_ == SDL_EventType::SDL_QUIT as u32 {
+                break 'game;
             }
+            input.handle_event(&event);
         }
 
-        SDL_GetMouseState(&mut input.mouse.x, &mut input.mouse.y);
+        // The canvas matches the renderer's output size in pixels, not the window size in
+        // the event. A minimized window may report an empty size.
+        let resized = input
+            .window_events
+            .iter()
+            .any(|e| matches!(e, WindowEvent::Resized { .. }));
+        if resized {
+            let (mut width, mut height) = (0, 0);
+            SDL_GetRendererOutputSize(renderer, &mut width, &mut height);
+            if width > 0 && height > 0 {
+                readonly_canvas.resize(width, height);
+            }
+        }
 
+        let steps = clock.begin_frame();
+        let start = clock.now();
+        for _ in 0..steps {
+            update(&input, fixed_delta);
+        }
         {
             let mut canvas = readonly_canvas.lock();
-            callback(&input, &mut canvas);
+            callback(&input, clock.time(), &mut canvas);
+            if canvas.data.stats_overlay {
+                canvas.draw_stats(clock.time());
+            }
         }
+        clock.end_frame(start);
 
-        SDL_RenderCopy(renderer, texture, null(), null());
+        SDL_RenderCopy(renderer, readonly_canvas.texture, null(), null());
         SDL_RenderPresent(renderer);
     }
 
+    SDL_DestroyTexture(readonly_canvas.texture);
+    SDL_DestroyRenderer(renderer);
     SDL_DestroyWindow(window);
 
     SDL_Quit();
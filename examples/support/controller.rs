@@ -0,0 +1,180 @@
+use super::canvas::*;
+use cgpp2::camera::*;
+use cgpp2::types::*;
+use sdl2_sys::SDL_Scancode;
+use std::f32::consts::FRAC_PI_2;
+
+// Keeps pitch away from the poles where yaw becomes ambiguous
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+fn yaw_pitch_rotation(yaw: f32, pitch: f32) -> Quat {
+    Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), yaw)
+        * Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), pitch)
+}
+
+// Circles the camera around `target`: drag with the left button to rotate, with the right
+// button or scroll to zoom and drag with the middle button to pan
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    // Radians, yaw 0 and pitch 0 look down -z
+    pub yaw: f32,
+    pub pitch: f32,
+    // Radians per pixel
+    pub rotate_speed: f32,
+    // Fraction of the distance per pixel
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+}
+
+impl OrbitController {
+    pub fn new(target: Vec3, distance: f32) -> OrbitController {
+        OrbitController {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            rotate_speed: 0.01,
+            zoom_speed: 0.005,
+            min_distance: 0.01,
+        }
+    }
+
+    pub fn update(&mut self, input: &Input, camera: &mut Camera) {
+        let mouse = &input.mouse;
+        let (dx, dy) = (mouse.dx as f32, mouse.dy as f32);
+        if mouse.is_held(MouseButton::Left) {
+            self.yaw -= dx * self.rotate_speed;
+            self.pitch = (self.pitch - dy * self.rotate_speed).clamp(-MAX_PITCH, MAX_PITCH);
+        } else if mouse.is_held(MouseButton::Right) {
+            self.distance *= 1.0 + dy * self.zoom_speed;
+        } else if mouse.is_held(MouseButton::Middle) {
+            // Moves the target in the view plane so it follows the cursor
+            let scale = self.distance * self.zoom_speed;
+            self.target += scale * (-dx * camera.right() + dy * camera.up());
+        }
+        // A notch of the wheel zooms by 10%
+        self.distance *= 0.9f32.powi(mouse.wheel_y);
+        self.distance = self.distance.max(self.min_distance);
+
+        camera.rotation = yaw_pitch_rotation(self.yaw, self.pitch);
+        camera.position = self.target - self.distance * camera.forward();
+    }
+}
+
+// Turns the camera around `target` like a trackball: the cursor is projected onto a sphere
+// filling the window and dragging with the left button rolls the sphere under it. The drag
+// rotates by twice the angle between the points on the sphere, so the orientation only
+// depends on where a drag starts and ends. Unlike `OrbitController` there's no fixed up
+// direction and the camera can roll. Scroll to zoom.
+//
+// See: Ken Shoemake, "ARCBALL: A User Interface for Specifying Three-Dimensional
+// Orientation Using a Mouse", Graphics Interface 1992
+pub struct ArcballController {
+    pub target: Vec3,
+    pub distance: f32,
+    pub rotation: Quat,
+    pub min_distance: f32,
+}
+
+impl ArcballController {
+    pub fn new(target: Vec3, distance: f32) -> ArcballController {
+        ArcballController {
+            target,
+            distance,
+            rotation: Quat::identity(),
+            min_distance: 0.01,
+        }
+    }
+
+    pub fn update(&mut self, input: &Input, camera: &mut Camera) {
+        let mouse = &input.mouse;
+        if mouse.is_held(MouseButton::Left) && (mouse.dx != 0 || mouse.dy != 0) {
+            let from = arcball_point(input, mouse.x - mouse.dx, mouse.y - mouse.dy);
+            let to = arcball_point(input, mouse.x, mouse.y);
+            // Rotates `from` to `to` in view space, the camera turns the opposite way
+            let axis = from.cross(to);
+            let drag = Quat::new(axis.e[0], axis.e[1], axis.e[2], from * to);
+            self.rotation = (self.rotation * drag.conjugate()).normalized();
+        }
+        // A notch of the wheel zooms by 10%
+        self.distance *= 0.9f32.powi(mouse.wheel_y);
+        self.distance = self.distance.max(self.min_distance);
+
+        camera.rotation = self.rotation;
+        camera.position = self.target - self.distance * camera.forward();
+    }
+}
+
+// Maps a position in window coordinates onto the unit sphere centered in the window and
+// facing the camera, points outside of it go to its silhouette
+fn arcball_point(input: &Input, x: i32, y: i32) -> Vec3 {
+    let size = input.window_width.min(input.window_height) as f32;
+    let px = (2 * x - input.window_width) as f32 / size;
+    let py = (input.window_height - 2 * y) as f32 / size;
+    let d = px * px + py * py;
+    if d > 1.0 {
+        Vec3::new(px, py, 0.0) / d.sqrt()
+    } else {
+        Vec3::new(px, py, (1.0 - d).sqrt())
+    }
+}
+
+// First-person camera: hold the right button and drag to look around, move with WASD and
+// Q/E for down/up, hold shift to move faster
+pub struct FlyController {
+    pub yaw: f32,
+    pub pitch: f32,
+    // World units per second
+    pub speed: f32,
+    pub boost: f32,
+    pub look_speed: f32,
+}
+
+impl FlyController {
+    pub fn new() -> FlyController {
+        FlyController {
+            yaw: 0.0,
+            pitch: 0.0,
+            speed: 1.0,
+            boost: 5.0,
+            look_speed: 0.005,
+        }
+    }
+
+    // `delta` is the time since the last update in seconds
+    pub fn update(&mut self, input: &Input, delta: f32, camera: &mut Camera) {
+        let mouse = &input.mouse;
+        if mouse.is_held(MouseButton::Right) {
+            self.yaw -= mouse.dx as f32 * self.look_speed;
+            self.pitch =
+                (self.pitch - mouse.dy as f32 * self.look_speed).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        camera.rotation = yaw_pitch_rotation(self.yaw, self.pitch);
+
+        let axis = |positive: SDL_Scancode, negative: SDL_Scancode| {
+            input.is_key_held(positive) as i32 as f32 - input.is_key_held(negative) as i32 as f32
+        };
+        let forward = axis(SDL_Scancode::SDL_SCANCODE_W, SDL_Scancode::SDL_SCANCODE_S);
+        let right = axis(SDL_Scancode::SDL_SCANCODE_D, SDL_Scancode::SDL_SCANCODE_A);
+        let up = axis(SDL_Scancode::SDL_SCANCODE_E, SDL_Scancode::SDL_SCANCODE_Q);
+        let direction =
+            forward * camera.forward() + right * camera.right() + up * Vec3::new(0.0, 1.0, 0.0);
+        if direction.len() == 0.0 {
+            return;
+        }
+
+        let speed = if input.is_key_held(SDL_Scancode::SDL_SCANCODE_LSHIFT) {
+            self.speed * self.boost
+        } else {
+            self.speed
+        };
+        camera.position += (speed * delta) * direction.normalized();
+    }
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1 +1,3 @@
 pub mod canvas;
+pub mod controller;
+pub mod time;
@@ -0,0 +1,106 @@
+use sdl2_sys::*;
+
+// Frame durations longer than this are cut short so a stall (e.g. dragging the window)
+// doesn't make the fixed updates fall further and further behind
+const MAX_DELTA: f32 = 0.25;
+
+// How often `fps` and `frame_time` are refreshed, in seconds
+const STATS_INTERVAL: f32 = 0.5;
+
+// Timing of the current frame, all times are in seconds unless noted otherwise
+#[derive(Copy, Clone)]
+pub struct Time {
+    // Since the previous frame
+    pub delta: f32,
+    // Since the first frame
+    pub elapsed: f64,
+    // Interval of the fixed updates, 0.0 if there are none
+    pub fixed_delta: f32,
+    // How far the current frame is between the last fixed update and the next one, in
+    // [0, 1), for interpolating state which is advanced in the fixed updates
+    pub alpha: f32,
+    // Frames per second and the average time spent updating and drawing a frame in
+    // milliseconds, which excludes waiting for VSYNC. Both are averaged over half a second.
+    pub fps: f32,
+    pub frame_time: f32,
+}
+
+// Drives the frame loop: measures frame durations and decides how many fixed updates to
+// run, see https://gafferongames.com/post/fix_your_timestep/
+pub(super) struct Clock {
+    time: Time,
+    frequency: f64,
+    last: u64,
+    accumulator: f32,
+    stats_frames: u32,
+    stats_duration: f32,
+    stats_busy: f32,
+}
+
+impl Clock {
+    pub fn new(fixed_delta: f32) -> Clock {
+        Clock {
+            time: Time {
+                delta: 0.0,
+                elapsed: 0.0,
+                fixed_delta,
+                alpha: 0.0,
+                fps: 0.0,
+                frame_time: 0.0,
+            },
+            frequency: unsafe { SDL_GetPerformanceFrequency() } as f64,
+            last: unsafe { SDL_GetPerformanceCounter() },
+            accumulator: 0.0,
+            stats_frames: 0,
+            stats_duration: 0.0,
+            stats_busy: 0.0,
+        }
+    }
+
+    pub fn time(&self) -> &Time {
+        &self.time
+    }
+
+    fn seconds_since(&self, start: u64) -> f32 {
+        let now = unsafe { SDL_GetPerformanceCounter() };
+        ((now - start) as f64 / self.frequency) as f32
+    }
+
+    pub fn now(&self) -> u64 {
+        unsafe { SDL_GetPerformanceCounter() }
+    }
+
+    // Starts a frame and returns the number of fixed updates to run before drawing it
+    pub fn begin_frame(&mut self) -> u32 {
+        let now = self.now();
+        let delta = ((now - self.last) as f64 / self.frequency) as f32;
+        self.last = now;
+
+        let time = &mut self.time;
+        time.delta = delta.min(MAX_DELTA);
+        time.elapsed += time.delta as f64;
+        if time.fixed_delta <= 0.0 {
+            return 0;
+        }
+
+        self.accumulator += time.delta;
+        let steps = (self.accumulator / time.fixed_delta) as u32;
+        self.accumulator -= steps as f32 * time.fixed_delta;
+        time.alpha = self.accumulator / time.fixed_delta;
+        steps
+    }
+
+    // `start` is the counter at the beginning of the frame's work
+    pub fn end_frame(&mut self, start: u64) {
+        self.stats_frames += 1;
+        self.stats_duration += self.time.delta;
+        self.stats_busy += self.seconds_since(start);
+        if self.stats_duration >= STATS_INTERVAL {
+            self.time.fps = self.stats_frames as f32 / self.stats_duration;
+            self.time.frame_time = self.stats_busy / self.stats_frames as f32 * 1000.0;
+            self.stats_frames = 0;
+            self.stats_duration = 0.0;
+            self.stats_busy = 0.0;
+        }
+    }
+}
@@ -1,174 +1,97 @@
+use std::cell::Cell;
+use std::f32::consts::FRAC_PI_2;
 use std::fs::File;
 use std::io::BufReader;
 
-use cgpp2::triangle::*;
+use cgpp2::camera::*;
+use cgpp2::lighting::*;
+use cgpp2::mesh::*;
+use cgpp2::pbr::*;
+use cgpp2::pipeline::*;
+use cgpp2::scene::*;
+use cgpp2::shadow::*;
+use cgpp2::texture::*;
+use cgpp2::transform::*;
 use cgpp2::types::*;
 
-use image::{DynamicImage, GenericImageView};
+use image::GenericImageView;
 
 use obj::TexturedVertex;
 
+use sdl2_sys::SDL_Scancode;
+
 pub mod support;
 
 use support::canvas::*;
-
-pub trait Varying {
-    fn as_vec(&self) -> Vec<f32>;
-    fn from_slice(slice: &[f32]) -> Self;
-}
-
-trait VertexShader<A, U> {
-    type V: Varying;
-
-    fn process(&self, attribute: &A, uniform: &U) -> VertexShaderOutput<Self::V>;
-}
-
-pub struct VertexShaderOutput<V: Varying> {
-    pub pos: Vec4,
-    pub varying: V,
-}
-
-pub struct FragmentShaderOutput {
-    pub color: Option<Vec4>,
-}
-
-trait FragmentShader<V: Varying, U> {
-    fn process(&self, varying: &V, uniform: &U) -> FragmentShaderOutput;
-}
-
-fn ndc_to_screen(p: Vec3, width: i32, height: i32) -> Vec3 {
-    Vec3::new(
-        (p.e[0] + 1.0) * (width as f32) / 2.0,
-        (p.e[1] + 1.0) * (height as f32) / 2.0,
-        (-p.e[2] + 1.0) / 2.0,
-    )
-}
-
-fn render<A, V: Varying, U>(
-    vertices: Vec<A>,
-    uniform: &U,
-    vs: &dyn VertexShader<A, U, V = V>,
-    fs: &dyn FragmentShader<V, U>,
-    width: i32,
-    height: i32,
-    canvas: &mut Canvas,
-) {
-    let mut zbuffer = vec![std::f32::MIN; (width * height) as usize];
-    let processed_vertices = vertices
-        .iter()
-        .map(|vertex| vs.process(vertex, uniform))
-        .map(|vo| {
-            let ndc = vo.pos.perspective_division();
-            let screen_pos = ndc_to_screen(ndc, width, height);
-            (screen_pos, vo.varying)
-        })
-        .collect::<Vec<(Vec3, V)>>();
-
-    for triangle in processed_vertices.chunks(3) {
-        let (a, av) = &triangle[0];
-        let (b, bv) = &triangle[1];
-        let (c, cv) = &triangle[2];
-
-        for p in fill_triangle_iter(
-            a.e[0],
-            a.e[1],
-            b.e[0],
-            b.e[1],
-            c.e[0],
-            c.e[1],
-            0,
-            0,
-            width - 1,
-            height - 1,
-        ) {
-            let w = Vec3::new(p.b0, p.b1, p.b2);
-            let x = p.x;
-            let y = height - 1 - p.y;
-            let z = Vec3::new(a.e[2], b.e[2], c.e[2]) * w;
-            if z > zbuffer[(y * width + x) as usize] {
-                zbuffer[(y * width + x) as usize] = z;
-                let wv = av
-                    .as_vec()
-                    .iter()
-                    .zip(bv.as_vec().iter())
-                    .zip(cv.as_vec().iter())
-                    .map(|((a, b), c)| Vec3::new(*a, *b, *c) * w)
-                    .collect::<Vec<f32>>();
-                let v = V::from_slice(&wv);
-                let fo = fs.process(&v, uniform);
-                if let Some(color) = fo.color {
-                    let color = p.aa * color;
-                    canvas.set_pixel(x, y, color.e[0], color.e[1], color.e[2], color.e[3]);
-                }
-            }
-        }
-    }
-
-    // dump z-buffer
-    //    for y in 0..height {
-    //        for x in 0..width {
-    //            let z = zbuffer[(y * width + x) as usize];
-    //            canvas.set_pixel(x, y, z, z, z, 1.0);
-    //        }
-    //    }
-}
-
-struct MyAttribute {
-    pub v: TexturedVertex,
-}
+use support::controller::*;
 
 struct MyVarying {
     u: f32,
     v: f32,
-    intensity: f32,
+    world_pos: Vec3,
+    normal: Vec3,
+    tangent: Vec4,
 }
 
 impl Varying for MyVarying {
     fn as_vec(&self) -> Vec<f32> {
-        vec![self.u, self.v, self.intensity]
+        vec![
+            self.u,
+            self.v,
+            self.world_pos.e[0],
+            self.world_pos.e[1],
+            self.world_pos.e[2],
+            self.normal.e[0],
+            self.normal.e[1],
+            self.normal.e[2],
+            self.tangent.e[0],
+            self.tangent.e[1],
+            self.tangent.e[2],
+            self.tangent.e[3],
+        ]
     }
 
     fn from_slice(slice: &[f32]) -> Self {
         MyVarying {
             u: slice[0],
             v: slice[1],
-            intensity: slice[2],
+            world_pos: Vec3::new(slice[2], slice[3], slice[4]),
+            normal: Vec3::new(slice[5], slice[6], slice[7]),
+            tangent: Vec4::new(slice[8], slice[9], slice[10], slice[11]),
         }
     }
 }
 
+// Blinn-Phong with the base color and normal textures of the node's material
 struct MyUniform<'a> {
     mvp: Mat4,
-    mvp_normal: Mat4,
-    light_dir_transformed: Vec3,
-    texture: &'a DynamicImage,
+    model: Mat4,
+    model_normal: Mat3,
+    eye: Vec3,
+    light: Light,
+    texture: &'a Texture,
+    normal_map: Option<&'a Texture>,
+    sampler: Sampler,
+    shadow_map: &'a ShadowMap,
 }
 
 struct MyVertexShader {}
 
-fn calc_intensity(n: Vec3, light_dir: Vec3) -> f32 {
-    n * (light_dir - 2.0 * (light_dir * n) * n)
-}
-
-impl<'a> VertexShader<MyAttribute, MyUniform<'a>> for MyVertexShader {
+impl<'a> VertexShader<Vertex, MyUniform<'a>> for MyVertexShader {
     type V = MyVarying;
 
-    fn process(&self, attribute: &MyAttribute, uniform: &MyUniform) -> VertexShaderOutput<Self::V> {
-        let v = attribute.v;
-        let p = Vec4::from_vec3(Vec3::with_elements(v.position), 1.0);
-        let c = uniform.mvp * p;
-        let intensity = calc_intensity(
-            (uniform.mvp_normal * Vec4::from_vec3(Vec3::with_elements(v.normal), 0.0))
-                .xyz()
-                .normalized(),
-            uniform.light_dir_transformed,
-        );
+    fn process(&self, v: &Vertex, uniform: &MyUniform) -> VertexShaderOutput<Self::V> {
+        let p = Vec4::from_vec3(v.position, 1.0);
+        let normal = uniform.model_normal * v.normal;
+        let tangent = (uniform.model * Vec4::from_vec3(v.tangent.xyz(), 0.0)).xyz();
         VertexShaderOutput {
-            pos: c,
+            pos: uniform.mvp * p,
             varying: MyVarying {
-                u: v.texture[0],
-                v: v.texture[1],
-                intensity,
+                u: v.uv[0],
+                v: v.uv[1],
+                world_pos: (uniform.model * p).perspective_division(),
+                normal,
+                tangent: Vec4::from_vec3(tangent, v.tangent.e[3]),
             },
         }
     }
@@ -177,32 +100,78 @@ impl<'a> VertexShader<MyAttribute, MyUniform<'a>> for MyVertexShader {
 struct MyFragmentShader {}
 
 impl<'a> FragmentShader<MyVarying, MyUniform<'a>> for MyFragmentShader {
-    fn process(&self, varying: &MyVarying, uniform: &MyUniform) -> FragmentShaderOutput {
-        let color = if varying.intensity > 0.0 {
-            let u = varying.u;
-            let v = varying.v;
-            assert!(u >= 0.0 && u <= 1.0);
-            assert!(v >= 0.0 && v <= 1.0);
-
-            let texture = &uniform.texture;
-            let tp = texture.get_pixel(
-                (u * (texture.width() - 1) as f32).round() as u32,
-                ((1.0 - v) * (texture.height() - 1) as f32).round() as u32,
-            );
+    fn process(
+        &self,
+        input: &FragmentShaderInput<MyVarying>,
+        uniform: &MyUniform,
+    ) -> FragmentShaderOutput {
+        let varying = &input.varying;
+        let ddx = Point::new(input.dfdx.u, input.dfdx.v);
+        let ddy = Point::new(input.dfdy.u, input.dfdy.v);
+        let tp = uniform
+            .sampler
+            .sample_grad(uniform.texture, varying.u, varying.v, ddx, ddy);
+
+        let n = match uniform.normal_map {
+            Some(normal_map) => {
+                let nt = decode_normal(
+                    uniform
+                        .sampler
+                        .sample_grad(normal_map, varying.u, varying.v, ddx, ddy),
+                );
+                (tbn(varying.normal, varying.tangent) * Vec4::from_vec3(nt, 0.0))
+                    .xyz()
+                    .normalized()
+            }
+            None => varying.normal.normalized(),
+        };
+        let v = (uniform.eye - varying.world_pos).normalized();
+        let material = Material {
+            diffuse: tp.xyz(),
+            specular: Vec3::new(0.3, 0.3, 0.3),
+            shininess: 32.0,
+        };
+        let lit = shade(
+            LightingModel::BlinnPhong,
+            &uniform.light,
+            &material,
+            varying.world_pos,
+            n,
+            v,
+        );
 
-            Some(Vec4::new(
-                (tp.data[0] as f32 / 255.0) * varying.intensity,
-                (tp.data[1] as f32 / 255.0) * varying.intensity,
-                (tp.data[2] as f32 / 255.0) * varying.intensity,
-                tp.data[3] as f32 / 255.0,
-            ))
+        let ambient = 0.1;
+        let visibility = if lambert(n, uniform.light.sample(varying.world_pos).l) > 0.0 {
+            uniform.shadow_map.visibility(varying.world_pos)
         } else {
-            None
+            0.0
         };
-        FragmentShaderOutput { color }
+        let c = ambient * material.diffuse + visibility * lit;
+
+        FragmentShaderOutput::color(Vec4::from_vec3(c, tp.e[3]))
     }
 }
 
+enum Controller {
+    Orbit,
+    Arcball(ArcballController),
+    Fly(FlyController),
+}
+
+fn load_texture(path: &str, srgb: bool) -> Option<Texture> {
+    let image = image::open(path).ok()?;
+    let data = image.flipv().to_rgba().into_raw();
+    let width = image.width() as i32;
+    let height = image.height() as i32;
+    let mut texture = if srgb {
+        Texture::from_srgba8(width, height, &data)
+    } else {
+        Texture::from_rgba8(width, height, &data)
+    };
+    texture.generate_mipmaps(MipmapFilter::Lanczos3);
+    Some(texture)
+}
+
 fn main() {
     let width = 800;
     let height = 800;
@@ -211,55 +180,153 @@ fn main() {
         BufReader::new(File::open("data/african_head.obj").expect("Failed to find obj file"));
     let model: obj::Obj<TexturedVertex> =
         obj::load_obj(model_input).expect("Failed to load obj file");
-    let texture =
-        image::open("data/african_head_diffuse.tga").expect("Failed to open texture file");
-
-    let mut rotation = 0.0;
-
-    setup(width, height, |_input, canvas| {
-        canvas.clear();
-
-        let camera = Mat4::look_at(
-            Vec3::new(0.0, 0.0, 2.0),
-            Vec3::new(0.0, 0.0, 0.0),
-            Vec3::new(0.0, 1.0, 0.0),
-        );
-
-        let projection = Mat4::frustum(-1.0, 1.0, -1.0, 1.0, 1.0, 3.0);
-
-        let model_transform = Mat4::rorate_y(rotation);
-        rotation += 0.01;
-
-        let vp = projection * camera;
-        let vp_normal = vp.transpose().inverse().unwrap();
-
-        let mvp = vp * model_transform;
-        let mvp_normal = mvp.transpose().inverse().unwrap();
-
-        let light_dir = Vec3::new(0.0, 0.0, -1.0).normalized();
-        let light_dir_transformed = (vp_normal * Vec4::from_vec3(light_dir, 0.0))
-            .xyz()
-            .normalized();
-
-        let vertices = model
-            .indices
+    let mut mesh = Mesh::new(
+        model
+            .vertices
             .iter()
-            .map(|index| MyAttribute {
-                v: model.vertices[*index as usize],
+            .map(|v| {
+                Vertex::new(
+                    Vec3::with_elements(v.position),
+                    Vec3::with_elements(v.normal),
+                    Vec2::new(v.texture[0], v.texture[1]),
+                )
             })
-            .collect();
+            .collect(),
+        model.indices.iter().map(|i| *i as u32).collect(),
+    );
+    mesh.generate_tangents();
 
-        let uniform = MyUniform {
-            mvp,
-            mvp_normal,
-            light_dir_transformed,
-            texture: &texture,
+    let texture =
+        load_texture("data/african_head_diffuse.tga", true).expect("Failed to open texture file");
+    // The tangent space normal map is optional
+    let normal_map = load_texture("data/african_head_nm_tangent.tga", false);
+
+    let mut framebuffer = Framebuffer::new(width, height, 1, true);
+
+    // The light circles the head at a fixed rate, the angle is advanced in the fixed
+    // updates and holds the previous and the current value for interpolation
+    let light_angle = Cell::new((0.0f32, 0.0f32));
+    let light_speed = 0.5;
+    let mut shadow_map = ShadowMap::new(1024, Mat4::identity());
+    // F1 toggles the FPS and frame time overlay
+    let mut show_stats = false;
+
+    let mut scene = Scene::new();
+    let head = scene.add_node(None, Transform::identity());
+    scene.node_mut(head).mesh = Some(scene.add_mesh(&mesh));
+    scene.node_mut(head).material = Some(scene.add_material(PbrMaterial {
+        base_color_texture: Some(&texture),
+        normal_texture: normal_map.as_ref(),
+        ..PbrMaterial::new(Vec4::new(1.0, 1.0, 1.0, 1.0))
+    }));
+    // Rotated around the y axis by the light angle
+    let light_node = scene.add_node(None, Transform::identity());
+    scene.node_mut(light_node).light = Some(Light::Directional(DirectionalLight {
+        direction: Vec3::new(-1.0, -1.0, -1.0).normalized(),
+        color: Vec3::one(),
+        intensity: 1.0,
+    }));
+
+    let mut camera = Camera::new(Projection::Perspective {
+        fovy: FRAC_PI_2,
+        near: 0.1,
+        far: 10.0,
+    });
+    let camera_node = scene.add_node(None, Transform::identity());
+    scene.node_mut(camera_node).camera = Some(camera.projection);
+    // Orbits the head, tab switches to an arcball, then to a first-person camera and back
+    let mut orbit = OrbitController::new(Vec3::zero(), 2.0);
+    let mut controller = Controller::Orbit;
+
+    let update = |_input: &Input, delta: f32| {
+        let (_, current) = light_angle.get();
+        light_angle.set((current, current + light_speed * delta));
+    };
+
+    setup_with_fixed_update(width, height, 1.0 / 60.0, update, |input, time, canvas| {
+        if input.is_key_pressed(SDL_Scancode::SDL_SCANCODE_F1) {
+            show_stats = !show_stats;
+        }
+        canvas.set_stats_overlay(show_stats);
+
+        // The projection's aspect ratio follows the framebuffer's viewport
+        framebuffer.resize(canvas.width(), canvas.height());
+        framebuffer.clear_color(0, Vec4::new(0.0, 0.0, 0.0, 0.0));
+        framebuffer.clear_depth(1.0);
+
+        if input.is_key_pressed(SDL_Scancode::SDL_SCANCODE_TAB) {
+            controller = match controller {
+                Controller::Orbit => Controller::Arcball(ArcballController {
+                    rotation: camera.rotation,
+                    ..ArcballController::new(orbit.target, orbit.distance)
+                }),
+                // Keeps looking in the same direction, the roll is lost
+                Controller::Arcball(_) => {
+                    let forward = camera.forward();
+                    Controller::Fly(FlyController {
+                        yaw: (-forward.e[0]).atan2(-forward.e[2]),
+                        pitch: forward.e[1].clamp(-1.0, 1.0).asin(),
+                        ..FlyController::new()
+                    })
+                }
+                Controller::Fly(_) => Controller::Orbit,
+            };
+        }
+        match &mut controller {
+            Controller::Orbit => orbit.update(input, &mut camera),
+            Controller::Arcball(arcball) => arcball.update(input, &mut camera),
+            Controller::Fly(fly) => fly.update(input, time.delta, &mut camera),
+        }
+        scene.node_mut(camera_node).set_local(camera.to_transform());
+
+        let (previous, current) = light_angle.get();
+        let angle = previous + (current - previous) * time.alpha;
+        scene
+            .node_mut(light_node)
+            .set_local(Transform::from_rotation(Quat::from_axis_angle(
+                Vec3::new(0.0, 1.0, 0.0),
+                angle,
+            )));
+        scene.update();
+
+        let light = scene.lights()[0];
+        let light_dir = match light {
+            Light::Directional(light) => light.direction,
+            _ => unreachable!(),
+        };
+        shadow_map.light_view_projection =
+            ShadowMap::directional_light_view_projection(light_dir, Vec3::zero(), 1.5);
+        shadow_map.clear();
+        scene.render_shadow_map(&mut shadow_map);
+
+        let mut state = RasterizerState::for_framebuffer(&framebuffer);
+        state.blend[0] = BlendMode::Over;
+        let sampler = Sampler {
+            max_anisotropy: 4.0,
+            ..Sampler::trilinear(WrapMode::Repeat)
         };
-
         let vs = MyVertexShader {};
         let fs = MyFragmentShader {};
-
-        render(vertices, &uniform, &vs, &fs, width, height, canvas)
+        scene.render_with(camera_node, &state.viewport, |draw| {
+            let uniform = MyUniform {
+                mvp: draw.mvp,
+                model: draw.model,
+                model_normal: draw.model.normal_matrix(),
+                eye: draw.eye,
+                light,
+                texture: draw
+                    .material
+                    .base_color_texture
+                    .expect("the material has no base color texture"),
+                normal_map: draw.material.normal_texture,
+                sampler,
+                shadow_map: &shadow_map,
+            };
+            render(draw.vertices, &uniform, &vs, &fs, &state, &mut framebuffer);
+        });
+
+        canvas.set_dither(true);
+        canvas.draw_texture(framebuffer.color_attachment(0));
     });
 
     /*
@@ -274,7 +341,7 @@ fn main() {
 
         let projection = Mat4::frustum(-1.0, 1.0, -1.0, 1.0, 1.0, 3.0);
 
-        let model_transform = Mat4::rorate_y(rotation);
+        let model_transform = Mat4::rotate_y(rotation);
         rotation += 0.01;
 
         let vp = projection * camera;
@@ -0,0 +1,121 @@
+use crate::types::*;
+
+// Approximate equality of floating point values and the math types, which are compared
+// element-wise.
+//
+// Two values are equal if they differ by at most `abs`, which is meant for values close to
+// 0, or if they are at most `ulps` representable values apart, which scales with the
+// magnitude of the values.
+//
+// See: https://randomascii.wordpress.com/2012/02/25/comparing-floating-point-numbers-2012-edition/
+pub trait ApproxEq {
+    type Scalar: Float;
+
+    fn approx_eq_with(&self, other: &Self, abs: Self::Scalar, ulps: u64) -> bool;
+
+    // A few bits of accumulated rounding error, enough for short chains of operations
+    fn approx_eq(&self, other: &Self) -> bool {
+        self.approx_eq_with(other, default_abs::<Self::Scalar>(), DEFAULT_ULPS)
+    }
+}
+
+pub const DEFAULT_ULPS: u64 = 64;
+
+pub fn default_abs<T: Float>() -> T {
+    T::EPSILON * T::from_f64(DEFAULT_ULPS as f64)
+}
+
+macro_rules! impl_approx_eq_float {
+    ($t:ident) => {
+        impl ApproxEq for $t {
+            type Scalar = $t;
+
+            fn approx_eq_with(&self, other: &$t, abs: $t, ulps: u64) -> bool {
+                let (a, b) = (*self, *other);
+                if a == b {
+                    return true;
+                }
+                if a.is_nan() || b.is_nan() {
+                    return false;
+                }
+                if (a - b).abs() <= abs {
+                    return true;
+                }
+                if a.is_sign_negative() != b.is_sign_negative() {
+                    return false;
+                }
+                Float::ulps_between(a, b) <= ulps
+            }
+        }
+    };
+}
+
+impl_approx_eq_float!(f32);
+impl_approx_eq_float!(f64);
+
+macro_rules! impl_approx_eq_elements {
+    ($t:ident) => {
+        impl<T: Float + ApproxEq<Scalar = T>> ApproxEq for $t<T> {
+            type Scalar = T;
+
+            fn approx_eq_with(&self, other: &Self, abs: T, ulps: u64) -> bool {
+                self.e
+                    .iter()
+                    .zip(other.e.iter())
+                    .all(|(a, b)| a.approx_eq_with(b, abs, ulps))
+            }
+        }
+    };
+}
+
+impl_approx_eq_elements!(Vector2);
+impl_approx_eq_elements!(Vector3);
+impl_approx_eq_elements!(Vector4);
+impl_approx_eq_elements!(Matrix3);
+impl_approx_eq_elements!(Matrix4);
+impl_approx_eq_elements!(Quaternion);
+
+impl<T: Float + ApproxEq<Scalar = T>> ApproxEq for Point2<T> {
+    type Scalar = T;
+
+    fn approx_eq_with(&self, other: &Self, abs: T, ulps: u64) -> bool {
+        self.x.approx_eq_with(&other.x, abs, ulps) && self.y.approx_eq_with(&other.y, abs, ulps)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_approx_eq_abs() {
+        assert!(0.0f32.approx_eq(&1e-7));
+        assert!(1e-7f32.approx_eq(&-1e-7));
+        assert!(!0.0f32.approx_eq(&1e-3));
+        assert!(0.0f32.approx_eq_with(&1e-3, 1e-2, 0));
+    }
+
+    #[test]
+    fn test_approx_eq_ulps() {
+        let a = 1.0e6f32;
+        let b = f32::from_bits(a.to_bits() + 3);
+        // Far more than any sensible absolute tolerance apart, but neighbours
+        assert!(b - a > 0.1);
+        assert!(a.approx_eq_with(&b, 0.0, 3));
+        assert!(!a.approx_eq_with(&b, 0.0, 2));
+        assert!(!1.0f64.approx_eq(&1.0001));
+        assert!(!f32::NAN.approx_eq(&f32::NAN));
+        assert!(f32::INFINITY.approx_eq(&f32::INFINITY));
+    }
+
+    #[test]
+    fn test_approx_eq_elements() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        assert!(a.approx_eq(&Vec3::new(1.0, 2.0, 3.000001)));
+        assert!(!a.approx_eq(&Vec3::new(1.0, 2.1, 3.0)));
+        assert!(Mat4::identity().approx_eq(&(Mat4::rotate_x(0.5) * Mat4::rotate_x(-0.5))));
+        assert!(!Mat4::identity().approx_eq(&Mat4::rotate_x(0.5)));
+        assert!(Point::new(1.0, 2.0).approx_eq(&Point::new(1.0, 2.0)));
+        assert!(DVec4::new(0.1, 0.2, 0.3, 0.4).approx_eq(&DVec4::new(0.1, 0.2, 0.1 + 0.2, 0.4)));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::pipeline::*;
+use crate::transform::*;
+use crate::types::*;
+
+// The aspect ratio isn't part of the projection, it's taken from the viewport the camera
+// renders to so it follows the window size
+#[derive(Copy, Clone)]
+pub enum Projection {
+    // `fovy` is the vertical field of view in radians
+    Perspective { fovy: f32, near: f32, far: f32 },
+    // `height` is the vertical extent of the view volume in world units
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+impl Projection {
+    pub fn to_mat4(&self, aspect: f32) -> Mat4 {
+        match *self {
+            Projection::Perspective { fovy, near, far } => {
+                Mat4::perspective(fovy, aspect, near, far)
+            }
+            Projection::Orthographic { height, near, far } => {
+                let top = height / 2.0;
+                let right = top * aspect;
+                Mat4::orthographic(-right, right, -top, top, near, far)
+            }
+        }
+    }
+
+    pub fn to_mat4_for_viewport(&self, viewport: &Viewport) -> Mat4 {
+        self.to_mat4(viewport.width / viewport.height)
+    }
+}
+
+// A camera at `position` looking down its local -z axis with +y up, `rotation` turns the
+// local axes into world space
+#[derive(Copy, Clone)]
+pub struct Camera {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub projection: Projection,
+}
+
+impl Camera {
+    pub fn new(projection: Projection) -> Camera {
+        Camera {
+            position: Vec3::zero(),
+            rotation: Quat::identity(),
+            projection,
+        }
+    }
+
+    // Turns the camera towards `target`, `up` must not be parallel to the view direction
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let z = (self.position - target).normalized();
+        let x = up.cross(z).normalized();
+        let y = z.cross(x);
+        self.rotation = Quat::from_mat4(&Mat4::from_basis(x, y, z)).normalized();
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.rotation.rotate(Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation.rotate(Vec3::new(1.0, 0.0, 0.0))
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation.rotate(Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    // Transform of a scene node carrying the camera
+    pub fn to_transform(&self) -> Transform {
+        Transform::new(self.position, self.rotation, Vec3::one())
+    }
+
+    // World to view space, the inverse of the camera's rigid transform
+    pub fn view(&self) -> Mat4 {
+        self.rotation.conjugate().to_mat4() * Mat4::translate(-self.position)
+    }
+
+    pub fn view_projection(&self, viewport: &Viewport) -> Mat4 {
+        self.projection.to_mat4_for_viewport(viewport) * self.view()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::approx::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn test_camera_view() {
+        let mut camera = Camera::new(Projection::Perspective {
+            fovy: FRAC_PI_2,
+            near: 0.1,
+            far: 10.0,
+        });
+        camera.position = Vec3::new(1.0, 2.0, 3.0);
+        let target = Vec3::new(0.0, 0.5, -1.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        camera.look_at(target, up);
+        assert!(camera
+            .view()
+            .approx_eq(&Mat4::look_at(camera.position, target, up)));
+        assert!(camera
+            .forward()
+            .approx_eq(&(target - camera.position).normalized()));
+        assert!(
+            (camera.view() * camera.to_transform().to_mat4()).approx_eq_with(
+                &Mat4::identity(),
+                1e-5,
+                DEFAULT_ULPS
+            )
+        );
+    }
+
+    #[test]
+    fn test_camera_projection() {
+        let viewport = Viewport::new(0.0, 0.0, 800.0, 400.0);
+        let perspective = Projection::Perspective {
+            fovy: FRAC_PI_2,
+            near: 1.0,
+            far: 3.0,
+        };
+        assert!(perspective
+            .to_mat4_for_viewport(&viewport)
+            .approx_eq(&Mat4::perspective(FRAC_PI_2, 2.0, 1.0, 3.0)));
+
+        let orthographic = Projection::Orthographic {
+            height: 4.0,
+            near: 1.0,
+            far: 3.0,
+        };
+        let m = orthographic.to_mat4_for_viewport(&viewport);
+        // The corners of the view volume map to the corners of the viewport
+        let corner = m.transform_point(Vec3::new(4.0, 2.0, -1.0));
+        assert!(corner.approx_eq(&Vec3::new(1.0, 1.0, -1.0)));
+    }
+}
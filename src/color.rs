@@ -0,0 +1,101 @@
+use crate::types::*;
+
+// sRGB transfer functions, values are in [0, 1]
+// See: https://en.wikipedia.org/wiki/SRGB#Transformation
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Alpha is always stored linearly
+pub fn srgb_to_linear_vec4(c: Vec4) -> Vec4 {
+    Vec4::new(
+        srgb_to_linear(c.e[0]),
+        srgb_to_linear(c.e[1]),
+        srgb_to_linear(c.e[2]),
+        c.e[3],
+    )
+}
+
+pub fn linear_to_srgb_vec4(c: Vec4) -> Vec4 {
+    Vec4::new(
+        linear_to_srgb(c.e[0]),
+        linear_to_srgb(c.e[1]),
+        linear_to_srgb(c.e[2]),
+        c.e[3],
+    )
+}
+
+// Threshold of the 4x4 Bayer matrix at pixel (x, y), in [-0.5, 0.5)
+// See: https://en.wikipedia.org/wiki/Ordered_dithering
+pub fn bayer4x4(x: i32, y: i32) -> f32 {
+    const BAYER: [u8; 16] = [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
+    let i = (y & 3) * 4 + (x & 3);
+    (BAYER[i as usize] as f32 + 0.5) / 16.0 - 0.5
+}
+
+// Quantizes a value in [0, 1] to 8 bits, `dither` is added in units of one
+// quantization step before rounding, e.g. `bayer4x4(x, y)` or 0.0 to disable.
+pub fn quantize8(c: f32, dither: f32) -> u8 {
+    (c * 255.0 + dither).round().clamp(0.0, 255.0) as u8
+}
+
+// Straight alpha "source over" blending, must be done in linear space
+pub fn blend_over(src: Vec4, dst: Vec4) -> Vec4 {
+    let a = src.e[3];
+    Vec4::new(
+        src.e[0] * a + dst.e[0] * (1.0 - a),
+        src.e[1] * a + dst.e[1] * (1.0 - a),
+        src.e[2] * a + dst.e[2] * (1.0 - a),
+        a + dst.e[3] * (1.0 - a),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_srgb_roundtrip() {
+        for i in 0..=255 {
+            let c = i as f32 / 255.0;
+            assert_eq!(quantize8(linear_to_srgb(srgb_to_linear(c)), 0.0), i as u8);
+        }
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 0.0001);
+        assert!((linear_to_srgb(0.5) - 0.735_357).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_bayer4x4() {
+        let mut thresholds = (0..16).map(|i| bayer4x4(i % 4, i / 4)).collect::<Vec<_>>();
+        thresholds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for (i, t) in thresholds.iter().enumerate() {
+            assert_eq!(*t, (i as f32 + 0.5) / 16.0 - 0.5);
+        }
+        assert_eq!(bayer4x4(1, 2), bayer4x4(5, -2));
+    }
+
+    #[test]
+    fn test_quantize8() {
+        assert_eq!(quantize8(-1.0, 0.0), 0);
+        assert_eq!(quantize8(2.0, 0.0), 255);
+        assert_eq!(quantize8(0.5, 0.0), 128);
+        // Dithering spreads a value between two steps over the matrix
+        let c = 100.25 / 255.0;
+        let count = (0..16)
+            .filter(|i| quantize8(c, bayer4x4(i % 4, i / 4)) == 101)
+            .count();
+        assert_eq!(count, 4);
+    }
+}
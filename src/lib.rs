@@ -1,3 +1,14 @@
+pub mod approx;
+pub mod camera;
+pub mod color;
+pub mod lighting;
 pub mod line;
+pub mod mesh;
+pub mod pbr;
+pub mod pipeline;
+pub mod scene;
+pub mod shadow;
+pub mod texture;
+pub mod transform;
 pub mod triangle;
 pub mod types;
@@ -0,0 +1,314 @@
+use crate::types::*;
+
+// Distance attenuation 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Copy, Clone)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn none() -> Attenuation {
+        Attenuation {
+            constant: 1.0,
+            linear: 0.0,
+            quadratic: 0.0,
+        }
+    }
+
+    // Physically based falloff
+    pub fn inverse_square() -> Attenuation {
+        Attenuation {
+            constant: 0.0,
+            linear: 0.0,
+            quadratic: 1.0,
+        }
+    }
+
+    pub fn factor(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance)
+    }
+}
+
+// `direction` is the direction the light travels in
+#[derive(Copy, Clone)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+#[derive(Copy, Clone)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub attenuation: Attenuation,
+}
+
+// Full intensity inside `inner_angle` and no light outside `outer_angle`, angles are in
+// radians from `direction`
+#[derive(Copy, Clone)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub attenuation: Attenuation,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+#[derive(Copy, Clone)]
+pub enum Light {
+    Directional(DirectionalLight),
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+// Light arriving at a surface point, `l` is the normalized direction towards the light
+#[derive(Copy, Clone)]
+pub struct LightSample {
+    pub l: Vec3,
+    pub radiance: Vec3,
+}
+
+impl LightSample {
+    fn none() -> LightSample {
+        LightSample {
+            l: Vec3::zero(),
+            radiance: Vec3::zero(),
+        }
+    }
+}
+
+impl Light {
+    // A point at the position of a point or spot light has no direction towards it, it
+    // receives no light instead of NaNs
+    pub fn sample(&self, p: Vec3) -> LightSample {
+        match self {
+            Light::Directional(light) => LightSample {
+                l: -light.direction.normalized(),
+                radiance: light.intensity * light.color,
+            },
+            Light::Point(light) => {
+                let d = light.position - p;
+                let distance = d.len();
+                if distance == 0.0 {
+                    return LightSample::none();
+                }
+                LightSample {
+                    l: d / distance,
+                    radiance: (light.intensity * light.attenuation.factor(distance)) * light.color,
+                }
+            }
+            Light::Spot(light) => {
+                let d = light.position - p;
+                let distance = d.len();
+                if distance == 0.0 {
+                    return LightSample::none();
+                }
+                let l = d / distance;
+                let cos = -l * light.direction.normalized();
+                let cone = smoothstep(light.outer_angle.cos(), light.inner_angle.cos(), cos);
+                LightSample {
+                    l,
+                    radiance: (light.intensity * light.attenuation.factor(distance) * cone)
+                        * light.color,
+                }
+            }
+        }
+    }
+
+    // Moves the light from the space `m` transforms from, e.g. a scene node's local space,
+    // into the space it transforms to
+    pub fn transformed(&self, m: &Mat4) -> Light {
+        match *self {
+            Light::Directional(light) => Light::Directional(DirectionalLight {
+                direction: m.transform_vector(light.direction).normalized(),
+                ..light
+            }),
+            Light::Point(light) => Light::Point(PointLight {
+                position: m.transform_point(light.position),
+                ..light
+            }),
+            Light::Spot(light) => Light::Spot(SpotLight {
+                position: m.transform_point(light.position),
+                direction: m.transform_vector(light.direction).normalized(),
+                ..light
+            }),
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    if edge0 == edge1 {
+        return if x < edge0 { 0.0 } else { 1.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// All directions are normalized, `n` is the surface normal, `l` points towards the
+// light and `v` towards the viewer.
+pub fn lambert(n: Vec3, l: Vec3) -> f32 {
+    (n * l).max(0.0)
+}
+
+pub fn phong(n: Vec3, l: Vec3, v: Vec3, shininess: f32) -> f32 {
+    if n * l <= 0.0 {
+        return 0.0;
+    }
+    let r = (-l).reflect(n);
+    (r * v).max(0.0).powf(shininess)
+}
+
+pub fn blinn_phong(n: Vec3, l: Vec3, v: Vec3, shininess: f32) -> f32 {
+    if n * l <= 0.0 {
+        return 0.0;
+    }
+    let h = (l + v).normalized();
+    (n * h).max(0.0).powf(shininess)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum LightingModel {
+    Lambert,
+    Phong,
+    BlinnPhong,
+}
+
+#[derive(Copy, Clone)]
+pub struct Material {
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub shininess: f32,
+}
+
+// Reflected light from `light` at `p` in linear space, `Lambert` ignores the specular
+// part of the material
+pub fn shade(
+    model: LightingModel,
+    light: &Light,
+    material: &Material,
+    p: Vec3,
+    n: Vec3,
+    v: Vec3,
+) -> Vec3 {
+    let sample = light.sample(p);
+    let diffuse = lambert(n, sample.l);
+    let specular = match model {
+        LightingModel::Lambert => 0.0,
+        LightingModel::Phong => phong(n, sample.l, v, material.shininess),
+        LightingModel::BlinnPhong => blinn_phong(n, sample.l, v, material.shininess),
+    };
+    (diffuse * material.diffuse + specular * material.specular).mul_elements(sample.radiance)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::approx::*;
+
+    #[test]
+    fn test_lambert() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        assert!(lambert(n, n).approx_eq(&1.0));
+        assert!(lambert(n, Vec3::new(1.0, 1.0, 0.0).normalized()).approx_eq(&0.5f32.sqrt()));
+        assert!(lambert(n, Vec3::new(0.0, -1.0, 0.0)).approx_eq(&0.0));
+    }
+
+    #[test]
+    fn test_phong() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let l = Vec3::new(1.0, 1.0, 0.0).normalized();
+        let mirror = Vec3::new(-1.0, 1.0, 0.0).normalized();
+        assert!(phong(n, l, mirror, 10.0).approx_eq(&1.0));
+        assert!(phong(n, l, n, 2.0).approx_eq(&0.5));
+        // No highlight when lit from behind
+        assert!(phong(n, -l, -mirror, 10.0).approx_eq(&0.0));
+    }
+
+    #[test]
+    fn test_blinn_phong() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let l = Vec3::new(1.0, 1.0, 0.0).normalized();
+        let mirror = Vec3::new(-1.0, 1.0, 0.0).normalized();
+        assert!(blinn_phong(n, l, mirror, 10.0).approx_eq(&1.0));
+        // Half vector is 22.5 degrees away from the normal
+        assert!(blinn_phong(n, l, n, 1.0).approx_eq(&(std::f32::consts::PI / 8.0).cos()));
+    }
+
+    #[test]
+    fn test_point_light() {
+        let light = Light::Point(PointLight {
+            position: Vec3::new(0.0, 2.0, 0.0),
+            color: Vec3::one(),
+            intensity: 8.0,
+            attenuation: Attenuation::inverse_square(),
+        });
+        let sample = light.sample(Vec3::new(0.0, 0.0, 0.0));
+        assert!(sample.l.e[1].approx_eq(&1.0));
+        assert!(sample.radiance.e[0].approx_eq(&2.0));
+
+        // At the light's position
+        let p = Vec3::new(0.0, 2.0, 0.0);
+        assert_eq!(light.sample(p).radiance.e[0], 0.0);
+        let material = Material {
+            diffuse: Vec3::one(),
+            specular: Vec3::one(),
+            shininess: 10.0,
+        };
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        for model in [
+            LightingModel::Lambert,
+            LightingModel::Phong,
+            LightingModel::BlinnPhong,
+        ] {
+            let c = shade(model, &light, &material, p, n, n);
+            assert_eq!(c.e[0], 0.0);
+        }
+    }
+
+    #[test]
+    fn test_spot_light() {
+        let spot = SpotLight {
+            position: Vec3::new(0.0, 1.0, 0.0),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            color: Vec3::one(),
+            intensity: 1.0,
+            attenuation: Attenuation::none(),
+            inner_angle: 0.3,
+            outer_angle: 0.6,
+        };
+        let light = Light::Spot(spot);
+        assert!(light.sample(Vec3::new(0.0, 0.0, 0.0)).radiance.e[0].approx_eq(&1.0));
+        // Between the cones, 0.45 radians off the axis
+        let falloff = light.sample(Vec3::new(0.45f32.tan(), 0.0, 0.0)).radiance.e[0];
+        assert!(falloff > 0.0 && falloff < 1.0);
+        assert!(light.sample(Vec3::new(1.0, 0.0, 0.0)).radiance.e[0].approx_eq(&0.0));
+    }
+
+    #[test]
+    fn test_shade() {
+        let light = Light::Directional(DirectionalLight {
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            color: Vec3::new(1.0, 0.5, 0.25),
+            intensity: 2.0,
+        });
+        let material = Material {
+            diffuse: Vec3::new(0.5, 0.5, 0.5),
+            specular: Vec3::one(),
+            shininess: 32.0,
+        };
+        let p = Vec3::new(0.0, 0.0, 0.0);
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let c = shade(LightingModel::Lambert, &light, &material, p, n, n);
+        assert!(c.e[0].approx_eq(&1.0));
+        assert!(c.e[1].approx_eq(&0.5));
+        assert!(c.e[2].approx_eq(&0.25));
+        let c = shade(LightingModel::BlinnPhong, &light, &material, p, n, n);
+        assert!(c.e[0].approx_eq(&3.0));
+    }
+}
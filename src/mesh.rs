@@ -0,0 +1,359 @@
+use crate::types::*;
+use std::collections::HashMap;
+
+// `tangent.w` is the handedness of the tangent frame, the bitangent is
+// `tangent.w * normal.cross(tangent.xyz())` as in glTF.
+#[derive(Copy, Clone)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+    pub tangent: Vec4,
+}
+
+impl Vertex {
+    pub fn new(position: Vec3, normal: Vec3, uv: Vec2) -> Vertex {
+        Vertex {
+            position,
+            normal,
+            uv,
+            tangent: Vec4::new(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+}
+
+// Object space bounding volumes, the sphere is cheaper to test and the box is tighter for
+// elongated meshes
+#[derive(Copy, Clone)]
+pub struct Bounds {
+    pub sphere: Sphere,
+    pub aabb: Aabb,
+}
+
+impl Bounds {
+    pub fn from_points(points: impl IntoIterator<Item = Vec3> + Clone) -> Bounds {
+        Bounds {
+            sphere: Sphere::from_points(points.clone()),
+            aabb: Aabb::from_points(points),
+        }
+    }
+}
+
+// Indexed triangle list
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    // Call `update_bounds` after moving the vertices
+    pub bounds: Bounds,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Mesh {
+        debug_assert!(indices.len().is_multiple_of(3));
+        let bounds = Bounds::from_points(vertices.iter().map(|v| v.position));
+        Mesh {
+            vertices,
+            indices,
+            bounds,
+        }
+    }
+
+    pub fn update_bounds(&mut self) {
+        self.bounds = Bounds::from_points(self.vertices.iter().map(|v| v.position));
+    }
+
+    // Computes per-vertex tangents the way MikkTSpace does, the convention of glTF and of
+    // most normal map bakers. Corners of triangles sharing a vertex form a group when the
+    // triangles are connected through edges with the same uv orientation. The triangle
+    // tangents are projected onto the plane of the vertex normal, weighted by the corner
+    // angle in that plane and averaged per group. Vertices in several groups, e.g. on the
+    // seam between mirrored uvs, are split into one vertex per group. MikkTSpace's default
+    // angular threshold never splits a group further, so that step is left out.
+    //
+    // See: http://www.mikktspace.com/
+    // See: https://github.com/mmikk/MikkTSpace/blob/master/mikktspace.c
+    pub fn generate_tangents(&mut self) {
+        let corners = self.indices.len();
+
+        // Per triangle tangent and whether the uv mapping preserves the orientation, `None`
+        // for triangles without an area in uv space
+        let faces: Vec<Option<(Vec3, bool)>> = self
+            .indices
+            .chunks(3)
+            .map(|face| {
+                let v = [
+                    &self.vertices[face[0] as usize],
+                    &self.vertices[face[1] as usize],
+                    &self.vertices[face[2] as usize],
+                ];
+                let e1 = v[1].position - v[0].position;
+                let e2 = v[2].position - v[0].position;
+                let [du1, dv1] = (v[1].uv - v[0].uv).e;
+                let [du2, dv2] = (v[2].uv - v[0].uv).e;
+                let r = du1 * dv2 - du2 * dv1;
+                let t = (dv2 * e1 - dv1 * e2) / r;
+                if r.abs() < f32::EPSILON || t.len() == 0.0 {
+                    None
+                } else {
+                    Some((t.normalized(), r > 0.0))
+                }
+            })
+            .collect();
+
+        // Union-find over the corners, corners of the same vertex in two triangles sharing
+        // an edge with the same orientation are merged
+        let mut groups: Vec<usize> = (0..corners).collect();
+        fn find(groups: &mut [usize], mut corner: usize) -> usize {
+            while groups[corner] != corner {
+                groups[corner] = groups[groups[corner]];
+                corner = groups[corner];
+            }
+            corner
+        }
+        let mut edges: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+        for (f, face) in self.indices.chunks(3).enumerate() {
+            if faces[f].is_none() {
+                continue;
+            }
+            for k in 0..3 {
+                let (a, b) = (face[k], face[(k + 1) % 3]);
+                edges.entry((a.min(b), a.max(b))).or_default().push(f);
+            }
+        }
+        for (&(a, b), adjacent) in edges.iter() {
+            for (i, &f) in adjacent.iter().enumerate() {
+                for &g in adjacent[i + 1..].iter() {
+                    if faces[f].map(|f| f.1) != faces[g].map(|g| g.1) {
+                        continue;
+                    }
+                    for vertex in [a, b] {
+                        let corner = |f: usize| {
+                            let k = self.indices[3 * f..3 * f + 3]
+                                .iter()
+                                .position(|&i| i == vertex);
+                            3 * f + k.unwrap()
+                        };
+                        let (c, d) = (find(&mut groups, corner(f)), find(&mut groups, corner(g)));
+                        groups[c] = d;
+                    }
+                }
+            }
+        }
+
+        let mut tangents = vec![Vec3::zero(); corners];
+        for (corner, &index) in self.indices.iter().enumerate() {
+            let (t, _) = match faces[corner / 3] {
+                Some(face) => face,
+                None => continue,
+            };
+            let face = corner - corner % 3;
+            let position = |k: usize| self.vertices[self.indices[face + k % 3] as usize].position;
+            let k = corner % 3;
+            let n = self.vertices[index as usize].normal;
+            let project = |v: Vec3| {
+                let v = v - (n * v) * n;
+                if v.len() > 0.0 {
+                    v.normalized()
+                } else {
+                    v
+                }
+            };
+
+            let a = project(position(k + 2) - position(k));
+            let c = project(position(k + 1) - position(k));
+            let angle = (a * c).clamp(-1.0, 1.0).acos();
+            let group = find(&mut groups, corner);
+            tangents[group] += angle * project(t);
+        }
+
+        // The first group of a vertex keeps it, later groups get a copy. Corners of
+        // triangles without an area in uv space take the vertex of the first group.
+        let mut vertices = vec![None; corners];
+        let mut used = vec![false; self.vertices.len()];
+        for corner in 0..corners {
+            let index = self.indices[corner] as usize;
+            let orientation = match faces[corner / 3] {
+                Some((_, orientation)) => orientation,
+                None => continue,
+            };
+            let group = find(&mut groups, corner);
+            let vertex = match vertices[group] {
+                Some(vertex) => vertex,
+                None => {
+                    let t = tangents[group];
+                    let t = if t.len() > f32::EPSILON {
+                        t.normalized()
+                    } else {
+                        any_perpendicular(self.vertices[index].normal)
+                    };
+                    let w = if orientation { 1.0 } else { -1.0 };
+                    let vertex = if used[index] {
+                        self.vertices.push(self.vertices[index]);
+                        self.vertices.len() - 1
+                    } else {
+                        used[index] = true;
+                        index
+                    };
+                    self.vertices[vertex].tangent = Vec4::from_vec3(t, w);
+                    vertices[group] = Some(vertex);
+                    vertex
+                }
+            };
+            self.indices[corner] = vertex as u32;
+        }
+
+        // Vertices only used by triangles without an area in uv space
+        for (vertex, _) in used.iter().enumerate().filter(|(_, used)| !**used) {
+            let n = self.vertices[vertex].normal;
+            self.vertices[vertex].tangent = Vec4::from_vec3(any_perpendicular(n), 1.0);
+        }
+    }
+}
+
+fn any_perpendicular(n: Vec3) -> Vec3 {
+    let axis = if n.e[0].abs() < 0.9 {
+        Vec3::new(1.0, 0.0, 0.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    };
+    n.cross(axis).normalized()
+}
+
+pub fn bitangent(normal: Vec3, tangent: Vec4) -> Vec3 {
+    tangent.e[3] * normal.cross(tangent.xyz())
+}
+
+// Matrix with the tangent, bitangent and normal as columns, transforms from tangent space
+// to the space `normal` and `tangent` are given in. Both should already be transformed by
+// the vertex shader, e.g. by the model's normal matrix.
+pub fn tbn(normal: Vec3, tangent: Vec4) -> Mat4 {
+    let n = normal.normalized();
+    let t = tangent.xyz();
+    let t = (t - (n * t) * n).normalized();
+    Mat4::from_basis(t, bitangent(n, Vec4::from_vec3(t, tangent.e[3])), n)
+}
+
+// Maps a texel of a tangent space normal map from [0, 1] to [-1, 1]
+pub fn decode_normal(texel: Vec4) -> Vec3 {
+    texel.xyz().map(|c| c * 2.0 - 1.0).normalized()
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::approx::*;
+
+    // Unit quad in the xy plane facing +z, with a corner at the origin
+    pub(crate) fn make_quad(uvs: [[f32; 2]; 4]) -> Mesh {
+        let uvs = uvs.map(|[u, v]| Vec2::new(u, v));
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        Mesh::new(
+            vec![
+                Vertex::new(Vec3::new(0.0, 0.0, 0.0), n, uvs[0]),
+                Vertex::new(Vec3::new(1.0, 0.0, 0.0), n, uvs[1]),
+                Vertex::new(Vec3::new(1.0, 1.0, 0.0), n, uvs[2]),
+                Vertex::new(Vec3::new(0.0, 1.0, 0.0), n, uvs[3]),
+            ],
+            vec![0, 1, 2, 0, 2, 3],
+        )
+    }
+
+    #[test]
+    fn test_mesh_bounds() {
+        let mut mesh = make_quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        assert!(mesh.bounds.aabb.min.approx_eq(&Vec3::zero()));
+        assert!(mesh.bounds.aabb.max.approx_eq(&Vec3::new(1.0, 1.0, 0.0)));
+        assert!(mesh
+            .bounds
+            .sphere
+            .center
+            .approx_eq(&Vec3::new(0.5, 0.5, 0.0)));
+        assert!((mesh.bounds.sphere.radius - 0.5f32.sqrt()).abs() < 0.0001);
+
+        mesh.vertices[2].position = Vec3::new(3.0, 1.0, 0.0);
+        mesh.update_bounds();
+        assert!(mesh.bounds.aabb.max.approx_eq(&Vec3::new(3.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn test_generate_tangents() {
+        let mut mesh = make_quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        mesh.generate_tangents();
+        assert_eq!(mesh.vertices.len(), 4);
+        for v in mesh.vertices.iter() {
+            assert!(v.tangent.xyz().approx_eq(&Vec3::new(1.0, 0.0, 0.0)));
+            assert_eq!(v.tangent.e[3], 1.0);
+            assert!(bitangent(v.normal, v.tangent).approx_eq(&Vec3::new(0.0, 1.0, 0.0)));
+        }
+    }
+
+    #[test]
+    fn test_generate_tangents_mirrored() {
+        // u is mirrored, the frame becomes left-handed
+        let mut mesh = make_quad([[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+        mesh.generate_tangents();
+        for v in mesh.vertices.iter() {
+            assert!(v.tangent.xyz().approx_eq(&Vec3::new(-1.0, 0.0, 0.0)));
+            assert_eq!(v.tangent.e[3], -1.0);
+            assert!(bitangent(v.normal, v.tangent).approx_eq(&Vec3::new(0.0, 1.0, 0.0)));
+        }
+    }
+
+    #[test]
+    fn test_generate_tangents_mirror_seam() {
+        // Two quads sharing the edge at x = 1, the uvs of the right one are mirrored
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let vertex = |x: f32, y: f32, u: f32| Vertex::new(Vec3::new(x, y, 0.0), n, Vec2::new(u, y));
+        let mut mesh = Mesh::new(
+            vec![
+                vertex(0.0, 0.0, 0.0),
+                vertex(1.0, 0.0, 1.0),
+                vertex(1.0, 1.0, 1.0),
+                vertex(0.0, 1.0, 0.0),
+                vertex(2.0, 0.0, 0.0),
+                vertex(2.0, 1.0, 0.0),
+            ],
+            vec![0, 1, 2, 0, 2, 3, 1, 4, 5, 1, 5, 2],
+        );
+        mesh.generate_tangents();
+
+        // The seam vertices are split, one copy per side
+        assert_eq!(mesh.vertices.len(), 8);
+        for (i, face) in mesh.indices.chunks(3).enumerate() {
+            let w = if i < 2 { 1.0 } else { -1.0 };
+            for &index in face {
+                let v = &mesh.vertices[index as usize];
+                assert!(v.tangent.xyz().approx_eq(&Vec3::new(w, 0.0, 0.0)));
+                assert_eq!(v.tangent.e[3], w);
+                assert!(bitangent(v.normal, v.tangent).approx_eq(&Vec3::new(0.0, 1.0, 0.0)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_tangents_orthogonal() {
+        // Tangents are orthogonalized against normals which aren't perpendicular to the
+        // triangle
+        let mut mesh = make_quad([[0.0, 0.0], [2.0, 0.0], [2.0, 3.0], [0.0, 3.0]]);
+        for v in mesh.vertices.iter_mut() {
+            v.normal = Vec3::new(0.3, 0.2, 1.0).normalized();
+        }
+        mesh.generate_tangents();
+        for v in mesh.vertices.iter() {
+            assert!((v.normal * v.tangent.xyz()).abs() < 0.0001);
+            assert!((v.tangent.xyz().len() - 1.0).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn test_tbn() {
+        let m = tbn(Vec3::new(0.0, 1.0, 0.0), Vec4::new(1.0, 0.0, 0.0, 1.0));
+        // Tangent space +z is the normal
+        let n = (m * Vec4::new(0.0, 0.0, 1.0, 0.0)).xyz();
+        assert!(n.approx_eq(&Vec3::new(0.0, 1.0, 0.0)));
+        let b = (m * Vec4::new(0.0, 1.0, 0.0, 0.0)).xyz();
+        assert!(b.approx_eq(&Vec3::new(0.0, 0.0, -1.0)));
+
+        assert!(decode_normal(Vec4::new(0.5, 0.5, 1.0, 1.0)).approx_eq(&Vec3::new(0.0, 0.0, 1.0)));
+    }
+}
@@ -0,0 +1,473 @@
+use std::f32::consts::PI;
+
+use crate::lighting::*;
+use crate::mesh::*;
+use crate::pipeline::*;
+use crate::texture::*;
+use crate::types::*;
+
+// Reflectance at normal incidence of dielectrics
+const DIELECTRIC_F0: f32 = 0.04;
+
+// Perceptually linear roughness is squared before it's used as the GGX alpha. Very small
+// values make the highlight of point lights vanish between pixels, so they are clamped.
+const MIN_ROUGHNESS: f32 = 0.03;
+
+// Metallic-roughness material as defined by glTF 2.0. Factors are multiplied with the
+// texels of the corresponding textures, all factors and textures are in linear space
+// except for `base_color_texture` and `emissive_texture` which should be sRGB textures.
+//
+// See: https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#materials
+#[derive(Copy, Clone)]
+pub struct PbrMaterial<'a> {
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: Vec3,
+    pub occlusion_strength: f32,
+    pub base_color_texture: Option<&'a Texture>,
+    // Roughness is read from the green channel and metalness from the blue channel
+    pub metallic_roughness_texture: Option<&'a Texture>,
+    // Ambient occlusion is read from the red channel
+    pub occlusion_texture: Option<&'a Texture>,
+    // Tangent space normal map
+    pub normal_texture: Option<&'a Texture>,
+    pub emissive_texture: Option<&'a Texture>,
+}
+
+impl<'a> PbrMaterial<'a> {
+    // Same defaults as glTF, a fully rough metal
+    pub fn new(base_color: Vec4) -> PbrMaterial<'a> {
+        PbrMaterial {
+            base_color,
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: Vec3::zero(),
+            occlusion_strength: 1.0,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            normal_texture: None,
+            emissive_texture: None,
+        }
+    }
+
+    // Resolves the material at a texture coordinate, `ddx` and `ddy` are the screen-space
+    // derivatives of the texture coordinate
+    pub fn surface(&self, sampler: &Sampler, uv: Point, ddx: Point, ddy: Point) -> Surface {
+        let sample = |texture: Option<&Texture>| {
+            texture.map(|texture| sampler.sample_grad(texture, uv.x, uv.y, ddx, ddy))
+        };
+
+        let mut base_color = self.base_color;
+        if let Some(texel) = sample(self.base_color_texture) {
+            base_color = base_color.mul_elements(texel);
+        }
+
+        let mut metallic = self.metallic;
+        let mut roughness = self.roughness;
+        if let Some(texel) = sample(self.metallic_roughness_texture) {
+            roughness *= texel.e[1];
+            metallic *= texel.e[2];
+        }
+
+        let occlusion = match sample(self.occlusion_texture) {
+            Some(texel) => 1.0 + self.occlusion_strength * (texel.e[0] - 1.0),
+            None => 1.0,
+        };
+
+        let emissive = match sample(self.emissive_texture) {
+            Some(texel) => self.emissive.mul_elements(texel.xyz()),
+            None => self.emissive,
+        };
+
+        Surface {
+            albedo: base_color.xyz(),
+            alpha: base_color.e[3],
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            occlusion,
+            emissive,
+        }
+    }
+}
+
+// Material properties at a single point of a surface
+#[derive(Copy, Clone)]
+pub struct Surface {
+    pub albedo: Vec3,
+    pub alpha: f32,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub occlusion: f32,
+    pub emissive: Vec3,
+}
+
+impl Surface {
+    // Dielectrics reflect 4% at normal incidence, metals tint the reflection with their
+    // albedo
+    pub fn f0(&self) -> Vec3 {
+        let dielectric = Vec3::splat(DIELECTRIC_F0);
+        dielectric + self.metallic * (self.albedo - dielectric)
+    }
+
+    // Metals have no diffuse reflection
+    pub fn diffuse_color(&self) -> Vec3 {
+        (1.0 - self.metallic) * self.albedo
+    }
+}
+
+fn alpha(roughness: f32) -> f32 {
+    let roughness = roughness.max(MIN_ROUGHNESS);
+    roughness * roughness
+}
+
+// Trowbridge-Reitz (GGX) normal distribution function
+//
+// See: https://learnopengl.com/PBR/Theory
+pub fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = alpha(roughness) * alpha(roughness);
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+// Smith's method with the Schlick-GGX approximation for each direction, using the
+// remapping of roughness for analytic lights from UE4
+//
+// See: https://cdn2.unrealengine.com/Resources/files/2013SiggraphPresentationsNotes-26915738.pdf
+pub fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness.max(MIN_ROUGHNESS) + 1.0;
+    let k = r * r / 8.0;
+    let g1 = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+    g1(n_dot_v) * g1(n_dot_l)
+}
+
+pub fn fresnel_schlick(cos: f32, f0: Vec3) -> Vec3 {
+    let t = (1.0 - cos).clamp(0.0, 1.0).powi(5);
+    f0 + t * (Vec3::one() - f0)
+}
+
+// Cook-Torrance microfacet specular plus Lambertian diffuse. All directions are
+// normalized, `n` is the surface normal, `l` points towards the light and `v` towards the
+// viewer. The result doesn't include the cosine term.
+pub fn cook_torrance(n: Vec3, v: Vec3, l: Vec3, surface: &Surface) -> Vec3 {
+    let n_dot_l = n * l;
+    if n_dot_l <= 0.0 {
+        return Vec3::zero();
+    }
+    // Normals from normal maps or interpolation may face away from the viewer
+    let n_dot_v = (n * v).max(1e-4);
+    let h = (l + v).normalized();
+    let n_dot_h = (n * h).max(0.0);
+    let v_dot_h = (v * h).max(0.0);
+
+    let d = distribution_ggx(n_dot_h, surface.roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, surface.roughness);
+    let f = fresnel_schlick(v_dot_h, surface.f0());
+    let specular = (d * g / (4.0 * n_dot_v * n_dot_l)) * f;
+
+    // Light reflected by the specular lobe doesn't enter the surface
+    let kd = Vec3::one() - f;
+    let diffuse = (1.0 / PI) * kd.mul_elements(surface.diffuse_color());
+
+    diffuse + specular
+}
+
+// Outgoing radiance towards `v` from `light` at `p`
+pub fn shade_pbr(light: &Light, surface: &Surface, p: Vec3, n: Vec3, v: Vec3) -> Vec3 {
+    let sample = light.sample(p);
+    let n_dot_l = n * sample.l;
+    if n_dot_l <= 0.0 {
+        return Vec3::zero();
+    }
+    n_dot_l * cook_torrance(n, v, sample.l, surface).mul_elements(sample.radiance)
+}
+
+#[derive(Copy, Clone)]
+pub struct PbrVarying {
+    pub world_pos: Vec3,
+    pub normal: Vec3,
+    pub tangent: Vec4,
+    pub uv: Point,
+}
+
+impl Varying for PbrVarying {
+    fn as_vec(&self) -> Vec<f32> {
+        vec![
+            self.world_pos.e[0],
+            self.world_pos.e[1],
+            self.world_pos.e[2],
+            self.normal.e[0],
+            self.normal.e[1],
+            self.normal.e[2],
+            self.tangent.e[0],
+            self.tangent.e[1],
+            self.tangent.e[2],
+            self.tangent.e[3],
+            self.uv.x,
+            self.uv.y,
+        ]
+    }
+
+    fn from_slice(slice: &[f32]) -> Self {
+        PbrVarying {
+            world_pos: Vec3::new(slice[0], slice[1], slice[2]),
+            normal: Vec3::new(slice[3], slice[4], slice[5]),
+            tangent: Vec4::new(slice[6], slice[7], slice[8], slice[9]),
+            uv: Point::new(slice[10], slice[11]),
+        }
+    }
+}
+
+// `model_normal` is `model.normal_matrix()`, `ambient` is a constant light
+// arriving from all directions which is scaled by the ambient occlusion of the material
+pub struct PbrUniform<'a> {
+    pub mvp: Mat4,
+    pub model: Mat4,
+    pub model_normal: Mat3,
+    pub eye: Vec3,
+    pub lights: &'a [Light],
+    pub ambient: Vec3,
+    pub material: PbrMaterial<'a>,
+    pub sampler: Sampler,
+}
+
+// Reference shaders rendering `Vertex` attributes with a `PbrMaterial`, the output is
+// linear color with straight alpha
+pub struct PbrVertexShader {}
+
+impl<'a> VertexShader<Vertex, PbrUniform<'a>> for PbrVertexShader {
+    type V = PbrVarying;
+
+    fn process(&self, vertex: &Vertex, uniform: &PbrUniform) -> VertexShaderOutput<Self::V> {
+        let p = Vec4::from_vec3(vertex.position, 1.0);
+        let normal = uniform.model_normal * vertex.normal;
+        let tangent = (uniform.model * Vec4::from_vec3(vertex.tangent.xyz(), 0.0)).xyz();
+        VertexShaderOutput {
+            pos: uniform.mvp * p,
+            varying: PbrVarying {
+                world_pos: (uniform.model * p).perspective_division(),
+                normal,
+                tangent: Vec4::from_vec3(tangent, vertex.tangent.e[3]),
+                uv: Point::new(vertex.uv[0], vertex.uv[1]),
+            },
+        }
+    }
+}
+
+pub struct PbrFragmentShader {}
+
+impl<'a> FragmentShader<PbrVarying, PbrUniform<'a>> for PbrFragmentShader {
+    fn process(
+        &self,
+        input: &FragmentShaderInput<PbrVarying>,
+        uniform: &PbrUniform,
+    ) -> FragmentShaderOutput {
+        let varying = &input.varying;
+        let material = &uniform.material;
+        let surface = material.surface(&uniform.sampler, varying.uv, input.dfdx.uv, input.dfdy.uv);
+
+        let n = match material.normal_texture {
+            Some(normal_texture) => {
+                let nt = decode_normal(uniform.sampler.sample_grad(
+                    normal_texture,
+                    varying.uv.x,
+                    varying.uv.y,
+                    input.dfdx.uv,
+                    input.dfdy.uv,
+                ));
+                (tbn(varying.normal, varying.tangent) * Vec4::from_vec3(nt, 0.0))
+                    .xyz()
+                    .normalized()
+            }
+            None => varying.normal.normalized(),
+        };
+        let v = (uniform.eye - varying.world_pos).normalized();
+
+        // Metals only reflect the ambient light specularly. Without a precomputed
+        // environment BRDF the specular reflectance is approximated by `f0`, which
+        // underestimates it at grazing angles.
+        let ambient = surface.diffuse_color() + surface.f0();
+        let mut c = surface.occlusion * uniform.ambient.mul_elements(ambient);
+        for light in uniform.lights.iter() {
+            c += shade_pbr(light, &surface, varying.world_pos, n, v);
+        }
+        c += surface.emissive;
+
+        FragmentShaderOutput::color(Vec4::from_vec3(c, surface.alpha))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::approx::*;
+
+    fn make_surface(metallic: f32, roughness: f32) -> Surface {
+        Surface {
+            albedo: Vec3::new(1.0, 0.5, 0.25),
+            alpha: 1.0,
+            metallic,
+            roughness,
+            occlusion: 1.0,
+            emissive: Vec3::zero(),
+        }
+    }
+
+    #[test]
+    fn test_distribution_ggx_normalized() {
+        // The projected area of the microfacets is the area of the macro surface
+        for roughness in [0.3, 0.6, 1.0] {
+            let steps = 4096;
+            let mut sum = 0.0;
+            for i in 0..steps {
+                let theta = (i as f32 + 0.5) / steps as f32 * PI / 2.0;
+                let d = distribution_ggx(theta.cos(), roughness);
+                sum += d * theta.cos() * theta.sin() * 2.0 * PI * (PI / 2.0 / steps as f32);
+            }
+            assert!(sum.approx_eq(&1.0));
+        }
+    }
+
+    #[test]
+    fn test_geometry_smith() {
+        assert!(geometry_smith(1.0, 1.0, 0.5).approx_eq(&1.0));
+        // More shadowing and masking at grazing angles and on rougher surfaces
+        assert!(geometry_smith(0.1, 1.0, 0.5) < geometry_smith(0.5, 1.0, 0.5));
+        assert!(geometry_smith(0.5, 0.5, 1.0) < geometry_smith(0.5, 0.5, 0.2));
+    }
+
+    #[test]
+    fn test_fresnel_schlick() {
+        let f0 = Vec3::new(0.04, 0.5, 1.0);
+        let f = fresnel_schlick(1.0, f0);
+        assert!(f.e[0].approx_eq(&0.04));
+        assert!(f.e[1].approx_eq(&0.5));
+        let f = fresnel_schlick(0.0, f0);
+        assert!(f.e[0].approx_eq(&1.0));
+        assert!(f.e[1].approx_eq(&1.0));
+    }
+
+    #[test]
+    fn test_cook_torrance() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let l = Vec3::new(1.0, 1.0, 0.0).normalized();
+        let mirror = Vec3::new(-1.0, 1.0, 0.0).normalized();
+
+        // Metals reflect their albedo and have no diffuse part
+        let metal = make_surface(1.0, 0.2);
+        let highlight = cook_torrance(n, mirror, l, &metal);
+        assert!(highlight.e[0] > highlight.e[1] && highlight.e[1] > highlight.e[2]);
+        let off = cook_torrance(n, Vec3::new(1.0, 0.2, 0.0).normalized(), l, &metal);
+        assert!(off.e[0] < 0.01);
+
+        // Rough dielectrics are close to Lambertian
+        let dielectric = make_surface(0.0, 1.0);
+        let c = cook_torrance(n, n, n, &dielectric);
+        assert!((c.e[0] - 1.0 / PI).abs() < 0.05);
+
+        // Lit from behind
+        let c = cook_torrance(n, mirror, -l, &dielectric);
+        assert_eq!(c.e[0], 0.0);
+    }
+
+    #[test]
+    fn test_material_textures() {
+        let metallic_roughness = Texture::with_texels(1, 1, vec![[0.0, 0.5, 0.25, 1.0]]);
+        let occlusion = Texture::with_texels(1, 1, vec![[0.2, 0.0, 0.0, 1.0]]);
+        let material = PbrMaterial {
+            roughness: 0.8,
+            occlusion_strength: 0.5,
+            metallic_roughness_texture: Some(&metallic_roughness),
+            occlusion_texture: Some(&occlusion),
+            ..PbrMaterial::new(Vec4::new(1.0, 1.0, 1.0, 0.5))
+        };
+        let sampler = Sampler::new(Filter::Nearest, WrapMode::Repeat);
+        let zero = Point::new(0.0, 0.0);
+        let surface = material.surface(&sampler, Point::new(0.5, 0.5), zero, zero);
+        assert!(surface.roughness.approx_eq(&0.4));
+        assert!(surface.metallic.approx_eq(&0.25));
+        assert!(surface.occlusion.approx_eq(&0.6));
+        assert!(surface.alpha.approx_eq(&0.5));
+    }
+
+    #[test]
+    fn test_fragment_shader() {
+        let lights = [Light::Directional(DirectionalLight {
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            color: Vec3::one(),
+            intensity: PI,
+        })];
+        let uniform = PbrUniform {
+            mvp: Mat4::identity(),
+            model: Mat4::identity(),
+            model_normal: Mat3::identity(),
+            eye: Vec3::new(0.0, 0.0, 1.0),
+            lights: &lights,
+            ambient: Vec3::zero(),
+            material: PbrMaterial {
+                metallic: 0.0,
+                emissive: Vec3::new(0.0, 0.0, 1.0),
+                ..PbrMaterial::new(Vec4::new(1.0, 0.0, 0.0, 1.0))
+            },
+            sampler: Sampler::new(Filter::Nearest, WrapMode::Repeat),
+        };
+        let varying = PbrVarying {
+            world_pos: Vec3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            tangent: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            uv: Point::new(0.0, 0.0),
+        };
+        let input = FragmentShaderInput {
+            varying,
+            dfdx: varying,
+            dfdy: varying,
+        };
+        let output = PbrFragmentShader {}.process(&input, &uniform);
+        let c = output.colors[0];
+        // Mostly diffuse red with a dim specular highlight, plus the emission
+        assert!(c.e[0] > 0.9 && c.e[0] < 1.1);
+        assert!(c.e[1] > 0.0 && c.e[1] < 0.1);
+        assert!(c.e[2] > 1.0);
+        assert_eq!(c.e[3], 1.0);
+    }
+
+    #[test]
+    fn test_fragment_shader_ambient() {
+        let varying = PbrVarying {
+            world_pos: Vec3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            tangent: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            uv: Point::new(0.0, 0.0),
+        };
+        let input = FragmentShaderInput {
+            varying,
+            dfdx: varying,
+            dfdy: varying,
+        };
+        let shade_ambient = |metallic: f32| {
+            let uniform = PbrUniform {
+                mvp: Mat4::identity(),
+                model: Mat4::identity(),
+                model_normal: Mat3::identity(),
+                eye: Vec3::new(0.0, 0.0, 1.0),
+                lights: &[],
+                ambient: Vec3::new(0.5, 0.5, 0.5),
+                material: PbrMaterial {
+                    metallic,
+                    ..PbrMaterial::new(Vec4::new(1.0, 0.0, 0.0, 1.0))
+                },
+                sampler: Sampler::new(Filter::Nearest, WrapMode::Repeat),
+            };
+            PbrFragmentShader {}.process(&input, &uniform).colors[0]
+        };
+
+        let dielectric = shade_ambient(0.0);
+        assert!(dielectric.e[0].approx_eq(&(0.5 * (1.0 + DIELECTRIC_F0))));
+        assert!(dielectric.e[1].approx_eq(&(0.5 * DIELECTRIC_F0)));
+        // No diffuse reflection, only the tinted specular one
+        let metal = shade_ambient(1.0);
+        assert!(metal.e[0].approx_eq(&0.5));
+        assert!(metal.e[1].approx_eq(&0.0));
+    }
+}
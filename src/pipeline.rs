@@ -0,0 +1,948 @@
+use crate::color::*;
+use crate::mesh::*;
+use crate::texture::*;
+use crate::triangle::*;
+use crate::types::*;
+
+pub trait Varying {
+    fn as_vec(&self) -> Vec<f32>;
+    fn from_slice(slice: &[f32]) -> Self;
+}
+
+pub trait VertexShader<A, U> {
+    type V: Varying;
+
+    fn process(&self, attribute: &A, uniform: &U) -> VertexShaderOutput<Self::V>;
+}
+
+pub struct VertexShaderOutput<V: Varying> {
+    pub pos: Vec4,
+    pub varying: V,
+}
+
+// Fragments are shaded in 2x2 quads, `dfdx` and `dfdy` hold the screen-space
+// derivatives of every component of `varying`.
+pub struct FragmentShaderInput<V: Varying> {
+    pub varying: V,
+    pub dfdx: V,
+    pub dfdy: V,
+}
+
+// `colors[i]` is written to color attachment `i` of the framebuffer, attachments without
+// a color are left untouched, the depth is written either way. A discarded fragment
+// writes neither colors nor depth.
+pub struct FragmentShaderOutput {
+    pub colors: Vec<Vec4>,
+    pub discarded: bool,
+}
+
+impl FragmentShaderOutput {
+    pub fn color(color: Vec4) -> FragmentShaderOutput {
+        FragmentShaderOutput {
+            colors: vec![color],
+            discarded: false,
+        }
+    }
+
+    // Only writes the depth, e.g. for depth-only passes without color attachments
+    pub fn depth_only() -> FragmentShaderOutput {
+        FragmentShaderOutput {
+            colors: Vec::new(),
+            discarded: false,
+        }
+    }
+
+    pub fn discard() -> FragmentShaderOutput {
+        FragmentShaderOutput {
+            colors: Vec::new(),
+            discarded: true,
+        }
+    }
+}
+
+pub trait FragmentShader<V: Varying, U> {
+    fn process(&self, input: &FragmentShaderInput<V>, uniform: &U) -> FragmentShaderOutput;
+}
+
+// Limit of color attachments a framebuffer can have, like in D3D11
+pub const MAX_COLOR_ATTACHMENTS: usize = 8;
+
+// Render target made of up to `MAX_COLOR_ATTACHMENTS` linear color attachments and an
+// optional depth attachment, which holds the depth in its red channel. Attachments are
+// plain textures so they can be bound as a texture in a later pass.
+//
+// Like textures, row 0 is the bottom row.
+pub struct Framebuffer {
+    width: i32,
+    height: i32,
+    color_attachments: Vec<Texture>,
+    depth_attachment: Option<Texture>,
+}
+
+impl Framebuffer {
+    pub fn new(width: i32, height: i32, color_attachments: usize, depth: bool) -> Framebuffer {
+        debug_assert!(color_attachments <= MAX_COLOR_ATTACHMENTS);
+        let mut framebuffer = Framebuffer {
+            width,
+            height,
+            color_attachments: (0..color_attachments)
+                .map(|_| Texture::new(width, height))
+                .collect(),
+            depth_attachment: if depth {
+                Some(Texture::new(width, height))
+            } else {
+                None
+            },
+        };
+        framebuffer.clear_depth(1.0);
+        framebuffer
+    }
+
+    // Recreates the attachments with the new size, e.g. after the window was resized. The
+    // contents are lost, the depth is cleared to 1.0 like in `new`.
+    pub fn resize(&mut self, width: i32, height: i32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+        *self = Framebuffer::new(
+            width,
+            height,
+            self.color_attachments.len(),
+            self.depth_attachment.is_some(),
+        );
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn color_attachments(&self) -> usize {
+        self.color_attachments.len()
+    }
+
+    pub fn color_attachment(&self, i: usize) -> &Texture {
+        &self.color_attachments[i]
+    }
+
+    pub fn color_attachment_mut(&mut self, i: usize) -> &mut Texture {
+        &mut self.color_attachments[i]
+    }
+
+    pub fn depth_attachment(&self) -> Option<&Texture> {
+        self.depth_attachment.as_ref()
+    }
+
+    pub fn depth_attachment_mut(&mut self) -> Option<&mut Texture> {
+        self.depth_attachment.as_mut()
+    }
+
+    pub fn clear_color(&mut self, i: usize, color: Vec4) {
+        let attachment = &mut self.color_attachments[i];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                attachment.set_texel(x, y, color);
+            }
+        }
+    }
+
+    pub fn clear_depth(&mut self, depth: f32) {
+        if let Some(attachment) = self.depth_attachment.as_mut() {
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    attachment.set_texel(x, y, Vec4::new(depth, 0.0, 0.0, 0.0));
+                }
+            }
+        }
+    }
+}
+
+// Range of the normalized device depth which is mapped to the viewport's depth range
+#[derive(Copy, Clone, PartialEq)]
+pub enum ClipDepth {
+    // Like in OpenGL, e.g. `Mat4::perspective`
+    NegativeOneToOne,
+    // Like in D3D and Vulkan, e.g. `Mat4::perspective_reversed_z`
+    ZeroToOne,
+}
+
+// Maps normalized device coordinates to window coordinates, (x, y) is the bottom-left
+// corner in pixels. Depth is mapped from the `clip_depth` range to [min_depth,
+// max_depth], smaller is closer unless the projection reverses it.
+#[derive(Copy, Clone)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+    pub clip_depth: ClipDepth,
+}
+
+impl Viewport {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Viewport {
+        Viewport {
+            x,
+            y,
+            width,
+            height,
+            min_depth: 0.0,
+            max_depth: 1.0,
+            clip_depth: ClipDepth::NegativeOneToOne,
+        }
+    }
+
+    pub fn ndc_to_screen(&self, p: Vec3) -> Vec3 {
+        // [0, 1] depth isn't remapped, with the default depth range it's stored as is
+        let depth = match self.clip_depth {
+            ClipDepth::NegativeOneToOne => (p.e[2] + 1.0) / 2.0,
+            ClipDepth::ZeroToOne => p.e[2],
+        };
+        Vec3::new(
+            self.x + (p.e[0] + 1.0) * self.width / 2.0,
+            self.y + (p.e[1] + 1.0) * self.height / 2.0,
+            self.min_depth + depth * (self.max_depth - self.min_depth),
+        )
+    }
+}
+
+// How a fragment's color is combined with the color stored in an attachment
+#[derive(Copy, Clone, PartialEq)]
+pub enum BlendMode {
+    // The color is written as is
+    Replace,
+    // Straight alpha "source over" blending, see `blend_over`
+    Over,
+}
+
+#[derive(Copy, Clone)]
+pub struct RasterizerState {
+    pub viewport: Viewport,
+    // Only pixels inside the rect are touched, `min` is inclusive and `max` exclusive
+    pub scissor: Option<Rect>,
+    // Depth bias (polygon offset) added to the depth of every fragment of a triangle:
+    //
+    //   bias = depth_bias * r + slope_scaled_depth_bias * max(|dz/dx|, |dz/dy|)
+    //
+    // where r is the smallest representable depth difference for the triangle. A
+    // positive `depth_bias_clamp` limits the bias from above, a negative one from below
+    // and 0.0 disables clamping.
+    //
+    // See: https://docs.microsoft.com/en-us/windows/win32/direct3d11/d3d10-graphics-programming-guide-output-merger-stage-depth-bias
+    pub depth_bias: f32,
+    pub slope_scaled_depth_bias: f32,
+    pub depth_bias_clamp: f32,
+    // A fragment passes if `depth_compare.compare(fragment_depth, stored_depth)`, use
+    // `Greater` with reversed-Z projections
+    pub depth_compare: CompareFunction,
+    // `blend[i]` applies to color attachment `i`
+    pub blend: [BlendMode; MAX_COLOR_ATTACHMENTS],
+}
+
+impl RasterizerState {
+    pub fn new(viewport: Viewport) -> RasterizerState {
+        RasterizerState {
+            viewport,
+            scissor: None,
+            depth_bias: 0.0,
+            slope_scaled_depth_bias: 0.0,
+            depth_bias_clamp: 0.0,
+            depth_compare: CompareFunction::Less,
+            blend: [BlendMode::Replace; MAX_COLOR_ATTACHMENTS],
+        }
+    }
+
+    // Viewport covering the whole framebuffer and no scissor
+    pub fn for_framebuffer(framebuffer: &Framebuffer) -> RasterizerState {
+        RasterizerState::new(Viewport::new(
+            0.0,
+            0.0,
+            framebuffer.width as f32,
+            framebuffer.height as f32,
+        ))
+    }
+
+    // Intersection of the framebuffer, the viewport and the scissor rect
+    fn clip_rect(&self, width: i32, height: i32) -> Rect {
+        let vp = &self.viewport;
+        let mut min = Point::new(vp.x.floor().max(0.0), vp.y.floor().max(0.0));
+        let mut max = Point::new(
+            (vp.x + vp.width).ceil().min(width as f32),
+            (vp.y + vp.height).ceil().min(height as f32),
+        );
+        if let Some(scissor) = self.scissor {
+            min = Point::new(min.x.max(scissor.min.x), min.y.max(scissor.min.y));
+            max = Point::new(max.x.min(scissor.max.x), max.y.min(scissor.max.y));
+        }
+        Rect::new(min, max)
+    }
+
+    // `a`, `b` and `c` are the window coordinates of the triangle
+    fn triangle_depth_bias(&self, a: Vec3, b: Vec3, c: Vec3) -> f32 {
+        if self.depth_bias == 0.0 && self.slope_scaled_depth_bias == 0.0 {
+            return 0.0;
+        }
+
+        // Depth gradient of the plane through the triangle
+        let area2 = (b.e[0] - a.e[0]) * (c.e[1] - a.e[1]) - (b.e[1] - a.e[1]) * (c.e[0] - a.e[0]);
+        let max_slope = if area2 != 0.0 {
+            let dzdx = ((b.e[2] - a.e[2]) * (c.e[1] - a.e[1])
+                - (c.e[2] - a.e[2]) * (b.e[1] - a.e[1]))
+                / area2;
+            let dzdy = ((c.e[2] - a.e[2]) * (b.e[0] - a.e[0])
+                - (b.e[2] - a.e[2]) * (c.e[0] - a.e[0]))
+                / area2;
+            dzdx.abs().max(dzdy.abs())
+        } else {
+            0.0
+        };
+
+        // For a floating point depth buffer r is one ulp of the largest depth
+        let max_z = a.e[2].abs().max(b.e[2].abs()).max(c.e[2].abs());
+        let r = if max_z > 0.0 {
+            2.0f32.powi(max_z.log2().floor() as i32 - 23)
+        } else {
+            0.0
+        };
+
+        let bias = self.depth_bias * r + self.slope_scaled_depth_bias * max_slope;
+        if self.depth_bias_clamp > 0.0 {
+            bias.min(self.depth_bias_clamp)
+        } else if self.depth_bias_clamp < 0.0 {
+            bias.max(self.depth_bias_clamp)
+        } else {
+            bias
+        }
+    }
+}
+
+// Object space bounds of a draw and the matrix which transforms them to clip space, usually
+// the `mvp` the vertex shader uses
+#[derive(Copy, Clone)]
+pub struct Culling {
+    pub bounds: Bounds,
+    pub model_view_projection: Mat4,
+}
+
+impl Culling {
+    pub fn new(bounds: Bounds, model_view_projection: Mat4) -> Culling {
+        Culling {
+            bounds,
+            model_view_projection,
+        }
+    }
+
+    // The frustum planes are extracted in object space so the bounds don't have to be
+    // transformed. Conservative, draws close to the frustum's corners may pass.
+    pub fn is_visible(&self) -> bool {
+        let frustum = Frustum::from_mat4(&self.model_view_projection);
+        frustum.intersects_sphere(&self.bounds.sphere) && frustum.intersects_aabb(&self.bounds.aabb)
+    }
+}
+
+// Like `render` but skips the draw if its bounds are fully outside of the view frustum,
+// returns whether it was drawn
+pub fn render_culled<A, V: Varying, U>(
+    vertices: &[A],
+    culling: &Culling,
+    uniform: &U,
+    vs: &dyn VertexShader<A, U, V = V>,
+    fs: &dyn FragmentShader<V, U>,
+    state: &RasterizerState,
+    framebuffer: &mut Framebuffer,
+) -> bool {
+    if !culling.is_visible() {
+        return false;
+    }
+    render(vertices, uniform, vs, fs, state, framebuffer);
+    true
+}
+
+pub fn render<A, V: Varying, U>(
+    vertices: &[A],
+    uniform: &U,
+    vs: &dyn VertexShader<A, U, V = V>,
+    fs: &dyn FragmentShader<V, U>,
+    state: &RasterizerState,
+    framebuffer: &mut Framebuffer,
+) {
+    let clip = state.clip_rect(framebuffer.width, framebuffer.height);
+    let processed_vertices = vertices
+        .iter()
+        .map(|vertex| vs.process(vertex, uniform))
+        .map(|vo| {
+            let ndc = vo.pos.perspective_division();
+            let screen_pos = state.viewport.ndc_to_screen(ndc);
+            (screen_pos, vo.varying)
+        })
+        .collect::<Vec<(Vec3, V)>>();
+
+    for triangle in processed_vertices.chunks(3) {
+        let (a, av) = &triangle[0];
+        let (b, bv) = &triangle[1];
+        let (c, cv) = &triangle[2];
+        let av = av.as_vec();
+        let bv = bv.as_vec();
+        let cv = cv.as_vec();
+        let bias = state.triangle_depth_bias(*a, *b, *c);
+
+        for quad in FillQuadIter::new(
+            Point::new(a.e[0], a.e[1]),
+            Point::new(b.e[0], b.e[1]),
+            Point::new(c.e[0], c.e[1]),
+            clip,
+        ) {
+            // Varyings are interpolated for helper pixels as well so that every
+            // covered pixel can be differentiated against its neighbours
+            let varyings = quad
+                .pixels
+                .iter()
+                .map(|p| {
+                    let w = Vec3::new(p.b0, p.b1, p.b2);
+                    av.iter()
+                        .zip(bv.iter())
+                        .zip(cv.iter())
+                        .map(|((a, b), c)| Vec3::new(*a, *b, *c) * w)
+                        .collect::<Vec<f32>>()
+                })
+                .collect::<Vec<_>>();
+            let quad_values = (0..av.len())
+                .map(|i| {
+                    [
+                        varyings[0][i],
+                        varyings[1][i],
+                        varyings[2][i],
+                        varyings[3][i],
+                    ]
+                })
+                .collect::<Vec<_>>();
+            let dfdx = quad_values
+                .iter()
+                .map(|f| TriangleRasterizedQuad::dfdx(*f))
+                .collect::<Vec<f32>>();
+            let dfdy = quad_values
+                .iter()
+                .map(|f| TriangleRasterizedQuad::dfdy(*f))
+                .collect::<Vec<f32>>();
+
+            for (i, p) in quad.pixels.iter().enumerate() {
+                if !quad.is_covered(i) {
+                    continue;
+                }
+
+                let w = Vec3::new(p.b0, p.b1, p.b2);
+                let x = p.x;
+                let y = p.y;
+                let z = Vec3::new(a.e[2], b.e[2], c.e[2]) * w + bias;
+                if let Some(depth) = framebuffer.depth_attachment.as_ref() {
+                    if !state.depth_compare.compare(z, depth.get_texel(x, y).e[0]) {
+                        continue;
+                    }
+                }
+
+                let input = FragmentShaderInput {
+                    varying: V::from_slice(&varyings[i]),
+                    dfdx: V::from_slice(&dfdx),
+                    dfdy: V::from_slice(&dfdy),
+                };
+                let fo = fs.process(&input, uniform);
+                if fo.discarded {
+                    continue;
+                }
+
+                if let Some(depth) = framebuffer.depth_attachment.as_mut() {
+                    depth.set_texel(x, y, Vec4::new(z, 0.0, 0.0, 0.0));
+                }
+
+                for ((color, attachment), blend) in fo
+                    .colors
+                    .into_iter()
+                    .zip(framebuffer.color_attachments.iter_mut())
+                    .zip(state.blend.iter())
+                {
+                    let color = match blend {
+                        BlendMode::Replace => color,
+                        BlendMode::Over => {
+                            // Coverage only scales alpha, `blend_over` applies it to the color
+                            let src =
+                                Vec4::new(color.e[0], color.e[1], color.e[2], p.aa * color.e[3]);
+                            blend_over(src, attachment.get_texel(x, y))
+                        }
+                    };
+                    attachment.set_texel(x, y, color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    struct Attribute {
+        pos: Vec3,
+        uv: [f32; 2],
+    }
+
+    struct UvVarying {
+        u: f32,
+        v: f32,
+    }
+
+    impl Varying for UvVarying {
+        fn as_vec(&self) -> Vec<f32> {
+            vec![self.u, self.v]
+        }
+
+        fn from_slice(slice: &[f32]) -> Self {
+            UvVarying {
+                u: slice[0],
+                v: slice[1],
+            }
+        }
+    }
+
+    struct PassThroughVertexShader {}
+
+    impl VertexShader<Attribute, Option<&Texture>> for PassThroughVertexShader {
+        type V = UvVarying;
+
+        fn process(
+            &self,
+            attribute: &Attribute,
+            _uniform: &Option<&Texture>,
+        ) -> VertexShaderOutput<Self::V> {
+            VertexShaderOutput {
+                pos: Vec4::from_vec3(attribute.pos, 1.0),
+                varying: UvVarying {
+                    u: attribute.uv[0],
+                    v: attribute.uv[1],
+                },
+            }
+        }
+    }
+
+    // Writes the uv to attachment 0 and a constant color to attachment 1, or samples
+    // the bound texture if there is one
+    struct TestFragmentShader {}
+
+    impl FragmentShader<UvVarying, Option<&Texture>> for TestFragmentShader {
+        fn process(
+            &self,
+            input: &FragmentShaderInput<UvVarying>,
+            uniform: &Option<&Texture>,
+        ) -> FragmentShaderOutput {
+            let v = &input.varying;
+            match uniform {
+                Some(texture) => FragmentShaderOutput::color(
+                    Sampler::new(Filter::Nearest, WrapMode::ClampToEdge).sample(texture, v.u, v.v),
+                ),
+                None => FragmentShaderOutput {
+                    colors: vec![Vec4::new(v.u, v.v, 0.0, 1.0), Vec4::new(0.0, 0.0, 1.0, 1.0)],
+                    discarded: false,
+                },
+            }
+        }
+    }
+
+    // Two triangles covering the whole viewport at the given depth
+    fn fullscreen_quad(z: f32) -> Vec<Attribute> {
+        let corners = [
+            ([-1.0, -1.0], [0.0, 0.0]),
+            ([1.0, -1.0], [1.0, 0.0]),
+            ([1.0, 1.0], [1.0, 1.0]),
+            ([-1.0, 1.0], [0.0, 1.0]),
+        ];
+        [0, 1, 2, 0, 2, 3]
+            .iter()
+            .map(|&i| Attribute {
+                pos: Vec3::new(corners[i].0[0], corners[i].0[1], z),
+                uv: corners[i].1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_render_multiple_render_targets() {
+        let mut framebuffer = Framebuffer::new(4, 4, 2, true);
+        render(
+            &fullscreen_quad(0.0),
+            &None,
+            &PassThroughVertexShader {},
+            &TestFragmentShader {},
+            &RasterizerState::for_framebuffer(&framebuffer),
+            &mut framebuffer,
+        );
+
+        let uv = framebuffer.color_attachment(0).get_texel(2, 1);
+        assert_eq!(uv.e[0], 0.625);
+        assert_eq!(uv.e[1], 0.375);
+        let blue = framebuffer.color_attachment(1).get_texel(2, 1);
+        assert_eq!(blue.e[2], 1.0);
+        let depth = framebuffer.depth_attachment().unwrap().get_texel(2, 1);
+        assert_eq!(depth.e[0], 0.5);
+    }
+
+    #[test]
+    fn test_render_culled() {
+        let mut framebuffer = Framebuffer::new(4, 4, 2, false);
+        let vs = PassThroughVertexShader {};
+        let fs = TestFragmentShader {};
+        let state = RasterizerState::for_framebuffer(&framebuffer);
+        let quad = fullscreen_quad(0.0);
+        let bounds = Bounds::from_points(quad.iter().map(|a| a.pos));
+
+        // The pass-through vertex shader has no transform, move the bounds out of the
+        // clip volume with the culling matrix alone
+        let outside = Culling::new(bounds, Mat4::translate(Vec3::new(3.0, 0.0, 0.0)));
+        assert!(!render_culled(
+            &quad,
+            &outside,
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer
+        ));
+        assert!(covered_pixels(&framebuffer).is_empty());
+
+        // Partially inside
+        let inside = Culling::new(bounds, Mat4::translate(Vec3::new(1.5, 0.0, 0.0)));
+        assert!(render_culled(
+            &quad,
+            &inside,
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer
+        ));
+        assert_eq!(covered_pixels(&framebuffer).len(), 16);
+    }
+
+    #[test]
+    fn test_culling_perspective() {
+        let bounds = Bounds::from_points([Vec3::new(-1.0, -1.0, -1.0), Vec3::one()]);
+        let vp = Mat4::perspective(FRAC_PI_2, 1.0, 1.0, 10.0)
+            * Mat4::look_at(
+                Vec3::zero(),
+                Vec3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            );
+        let model = |x: f32, z: f32| vp * Mat4::translate(Vec3::new(x, 0.0, z));
+        assert!(Culling::new(bounds, model(0.0, -5.0)).is_visible());
+        // Behind the camera, beyond the far plane and beside the frustum
+        assert!(!Culling::new(bounds, model(0.0, 5.0)).is_visible());
+        assert!(!Culling::new(bounds, model(0.0, -12.0)).is_visible());
+        assert!(!Culling::new(bounds, model(8.0, -5.0)).is_visible());
+        // Straddling the right plane
+        assert!(Culling::new(bounds, model(5.5, -5.0)).is_visible());
+    }
+
+    #[test]
+    fn test_framebuffer_resize() {
+        let mut framebuffer = Framebuffer::new(4, 4, 2, true);
+        framebuffer.resize(6, 3);
+        assert_eq!(framebuffer.width(), 6);
+        assert_eq!(framebuffer.height(), 3);
+        assert_eq!(framebuffer.color_attachments(), 2);
+        assert_eq!(framebuffer.color_attachment(1).width(), 6);
+        let depth = framebuffer.depth_attachment().unwrap();
+        assert_eq!(depth.height(), 3);
+        assert_eq!(depth.get_texel(5, 2).e[0], 1.0);
+    }
+
+    #[test]
+    fn test_render_blend() {
+        let mut framebuffer = Framebuffer::new(4, 4, 1, false);
+        let vs = PassThroughVertexShader {};
+        let fs = TestFragmentShader {};
+        let mut texture = Texture::new(1, 1);
+        texture.set_texel(0, 0, Vec4::new(1.0, 0.0, 0.0, 0.5));
+        let mut state = RasterizerState::for_framebuffer(&framebuffer);
+
+        // Blending is off by default
+        framebuffer.clear_color(0, Vec4::new(0.0, 0.0, 1.0, 1.0));
+        render(
+            &fullscreen_quad(0.0),
+            &Some(&texture),
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        let c = framebuffer.color_attachment(0).get_texel(1, 1);
+        assert_eq!((c.e[0], c.e[2], c.e[3]), (1.0, 0.0, 0.5));
+
+        framebuffer.clear_color(0, Vec4::new(0.0, 0.0, 1.0, 1.0));
+        state.blend[0] = BlendMode::Over;
+        render(
+            &fullscreen_quad(0.0),
+            &Some(&texture),
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        let c = framebuffer.color_attachment(0).get_texel(1, 1);
+        assert_eq!((c.e[0], c.e[2], c.e[3]), (0.5, 0.5, 1.0));
+    }
+
+    #[test]
+    fn test_render_depth_test() {
+        let mut framebuffer = Framebuffer::new(4, 4, 2, true);
+        let vs = PassThroughVertexShader {};
+        let fs = TestFragmentShader {};
+        let state = RasterizerState::for_framebuffer(&framebuffer);
+        render(
+            &fullscreen_quad(0.5),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        framebuffer.clear_color(1, Vec4::new(0.0, 0.0, 0.0, 0.0));
+        render(
+            &fullscreen_quad(0.8),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        assert_eq!(framebuffer.color_attachment(1).get_texel(1, 1).e[2], 0.0);
+        render(
+            &fullscreen_quad(-0.5),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        assert_eq!(framebuffer.color_attachment(1).get_texel(1, 1).e[2], 1.0);
+    }
+
+    #[test]
+    fn test_render_reversed_depth_test() {
+        let mut framebuffer = Framebuffer::new(4, 4, 2, true);
+        framebuffer.clear_depth(0.0);
+        let vs = PassThroughVertexShader {};
+        let fs = TestFragmentShader {};
+        let mut state = RasterizerState::for_framebuffer(&framebuffer);
+        state.depth_compare = CompareFunction::Greater;
+        render(
+            &fullscreen_quad(0.5),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        framebuffer.clear_color(1, Vec4::new(0.0, 0.0, 0.0, 0.0));
+        render(
+            &fullscreen_quad(-0.5),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        assert_eq!(framebuffer.color_attachment(1).get_texel(1, 1).e[2], 0.0);
+        render(
+            &fullscreen_quad(0.8),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        assert_eq!(framebuffer.color_attachment(1).get_texel(1, 1).e[2], 1.0);
+    }
+
+    #[test]
+    fn test_render_to_texture() {
+        let mut offscreen = Framebuffer::new(4, 4, 2, false);
+        let vs = PassThroughVertexShader {};
+        let fs = TestFragmentShader {};
+        let state = RasterizerState::for_framebuffer(&offscreen);
+        render(
+            &fullscreen_quad(0.0),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut offscreen,
+        );
+
+        // Sample the first pass' uv attachment in the second pass
+        let mut framebuffer = Framebuffer::new(4, 4, 1, true);
+        let texture = offscreen.color_attachment(0);
+        render(
+            &fullscreen_quad(0.0),
+            &Some(texture),
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        let c = framebuffer.color_attachment(0).get_texel(3, 0);
+        assert_eq!(c.e[0], 0.875);
+        assert_eq!(c.e[1], 0.125);
+    }
+
+    // Returns which pixels of attachment 1 were written
+    fn covered_pixels(framebuffer: &Framebuffer) -> Vec<(i32, i32)> {
+        let mut pixels = Vec::new();
+        for y in 0..framebuffer.height() {
+            for x in 0..framebuffer.width() {
+                if framebuffer.color_attachment(1).get_texel(x, y).e[2] == 1.0 {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_render_full_viewport() {
+        let mut framebuffer = Framebuffer::new(3, 3, 2, false);
+        render(
+            &fullscreen_quad(0.0),
+            &None,
+            &PassThroughVertexShader {},
+            &TestFragmentShader {},
+            &RasterizerState::for_framebuffer(&framebuffer),
+            &mut framebuffer,
+        );
+        assert_eq!(covered_pixels(&framebuffer).len(), 9);
+    }
+
+    #[test]
+    fn test_render_viewport() {
+        let mut framebuffer = Framebuffer::new(4, 4, 2, true);
+        let mut state = RasterizerState::new(Viewport::new(2.0, 0.0, 2.0, 2.0));
+        state.viewport.min_depth = 0.5;
+        render(
+            &fullscreen_quad(-1.0),
+            &None,
+            &PassThroughVertexShader {},
+            &TestFragmentShader {},
+            &state,
+            &mut framebuffer,
+        );
+        assert_eq!(
+            covered_pixels(&framebuffer),
+            vec![(2, 0), (3, 0), (2, 1), (3, 1)]
+        );
+
+        // uv spans the viewport, not the framebuffer
+        let uv = framebuffer.color_attachment(0).get_texel(2, 0);
+        assert_eq!(uv.e[0], 0.25);
+        assert_eq!(uv.e[1], 0.25);
+        let depth = framebuffer.depth_attachment().unwrap().get_texel(2, 0);
+        assert_eq!(depth.e[0], 0.5);
+    }
+
+    #[test]
+    fn test_render_clip_depth() {
+        let mut framebuffer = Framebuffer::new(4, 4, 2, true);
+        let mut state = RasterizerState::for_framebuffer(&framebuffer);
+        state.viewport.clip_depth = ClipDepth::ZeroToOne;
+        // Depth close to 0 keeps its precision, e.g. the far range of reversed-Z
+        render(
+            &fullscreen_quad(1.0e-6),
+            &None,
+            &PassThroughVertexShader {},
+            &TestFragmentShader {},
+            &state,
+            &mut framebuffer,
+        );
+        let depth = framebuffer.depth_attachment().unwrap().get_texel(1, 1);
+        assert!((depth.e[0] - 1.0e-6).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn test_render_scissor() {
+        let mut framebuffer = Framebuffer::new(4, 4, 2, false);
+        let mut state = RasterizerState::for_framebuffer(&framebuffer);
+        state.scissor = Some(Rect::new(Point::new(1.0, 2.0), Point::new(3.0, 3.0)));
+        render(
+            &fullscreen_quad(0.0),
+            &None,
+            &PassThroughVertexShader {},
+            &TestFragmentShader {},
+            &state,
+            &mut framebuffer,
+        );
+        assert_eq!(covered_pixels(&framebuffer), vec![(1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn test_triangle_depth_bias() {
+        let mut state = RasterizerState::new(Viewport::new(0.0, 0.0, 4.0, 4.0));
+        let a = Vec3::new(0.0, 0.0, 0.5);
+        let b = Vec3::new(4.0, 0.0, 0.5);
+        let c = Vec3::new(0.0, 4.0, 0.7);
+        assert_eq!(state.triangle_depth_bias(a, b, c), 0.0);
+
+        state.depth_bias = 2.0;
+        assert_eq!(state.triangle_depth_bias(a, b, c), 2.0 * 2.0f32.powi(-24));
+
+        state.depth_bias = 0.0;
+        state.slope_scaled_depth_bias = 2.0;
+        assert!((state.triangle_depth_bias(a, b, c) - 0.1).abs() < 0.00001);
+
+        state.depth_bias_clamp = 0.01;
+        assert_eq!(state.triangle_depth_bias(a, b, c), 0.01);
+
+        state.slope_scaled_depth_bias = -2.0;
+        state.depth_bias_clamp = -0.01;
+        assert_eq!(state.triangle_depth_bias(a, b, c), -0.01);
+    }
+
+    #[test]
+    fn test_render_depth_bias() {
+        let mut framebuffer = Framebuffer::new(4, 4, 2, true);
+        let vs = PassThroughVertexShader {};
+        let fs = TestFragmentShader {};
+        let mut state = RasterizerState::for_framebuffer(&framebuffer);
+        render(
+            &fullscreen_quad(0.0),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+
+        // Coplanar geometry fails the depth test without a bias
+        framebuffer.clear_color(1, Vec4::new(0.0, 0.0, 0.0, 0.0));
+        render(
+            &fullscreen_quad(0.0),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        assert!(covered_pixels(&framebuffer).is_empty());
+
+        state.depth_bias = -1.0;
+        render(
+            &fullscreen_quad(0.0),
+            &None,
+            &vs,
+            &fs,
+            &state,
+            &mut framebuffer,
+        );
+        assert_eq!(covered_pixels(&framebuffer).len(), 16);
+    }
+}
@@ -0,0 +1,469 @@
+use crate::camera::*;
+use crate::lighting::*;
+use crate::mesh::*;
+use crate::pbr::*;
+use crate::pipeline::*;
+use crate::shadow::*;
+use crate::texture::*;
+use crate::transform::*;
+use crate::types::*;
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct NodeId(usize);
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct MeshId(usize);
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct MaterialId(usize);
+
+// A node of the scene graph. Its world matrix is `parent.world * local` and is cached
+// until the local transform of the node or one of its ancestors changes.
+pub struct Node {
+    local: Transform,
+    world: Mat4,
+    dirty: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    // Drawn with `material`, or a white default material if there is none
+    pub mesh: Option<MeshId>,
+    pub material: Option<MaterialId>,
+    // In the node's local space
+    pub light: Option<Light>,
+    // Camera looking down the node's -z axis with +y up, see `Camera`
+    pub camera: Option<Projection>,
+}
+
+impl Node {
+    fn new(local: Transform, parent: Option<NodeId>) -> Node {
+        Node {
+            local,
+            world: Mat4::identity(),
+            dirty: true,
+            parent,
+            children: Vec::new(),
+            mesh: None,
+            material: None,
+            light: None,
+            camera: None,
+        }
+    }
+
+    pub fn local(&self) -> &Transform {
+        &self.local
+    }
+
+    pub fn set_local(&mut self, local: Transform) {
+        self.local = local;
+        self.dirty = true;
+    }
+
+    // As of the last `Scene::update`
+    pub fn world(&self) -> &Mat4 {
+        &self.world
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+// A mesh to draw, passed to the callback of `Scene::render_with`. The lights are in world
+// space and nodes without a material get the default one of `Scene::render`.
+pub struct Draw<'s, 'a> {
+    pub vertices: &'s [Vertex],
+    pub model: Mat4,
+    pub mvp: Mat4,
+    // The camera's position in world space
+    pub eye: Vec3,
+    pub lights: &'s [Light],
+    pub material: &'s PbrMaterial<'a>,
+}
+
+// Meshes are stored as triangle lists, ready to be passed to `render`
+struct SceneMesh {
+    bounds: Bounds,
+    vertices: Vec<Vertex>,
+}
+
+// Nodes, meshes and materials are owned by the scene and referenced by ids, which stay
+// valid for the lifetime of the scene. Meshes and materials can be shared by any number of
+// nodes.
+pub struct Scene<'a> {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+    meshes: Vec<SceneMesh>,
+    materials: Vec<PbrMaterial<'a>>,
+    pub ambient: Vec3,
+    pub sampler: Sampler,
+}
+
+impl<'a> Scene<'a> {
+    pub fn new() -> Scene<'a> {
+        Scene {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            meshes: Vec::new(),
+            materials: Vec::new(),
+            ambient: Vec3::zero(),
+            sampler: Sampler::trilinear(WrapMode::Repeat),
+        }
+    }
+
+    pub fn add_node(&mut self, parent: Option<NodeId>, local: Transform) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node::new(local, parent));
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    pub fn add_mesh(&mut self, mesh: &Mesh) -> MeshId {
+        let vertices = mesh
+            .indices
+            .iter()
+            .map(|i| mesh.vertices[*i as usize])
+            .collect();
+        self.meshes.push(SceneMesh {
+            bounds: mesh.bounds,
+            vertices,
+        });
+        MeshId(self.meshes.len() - 1)
+    }
+
+    pub fn add_material(&mut self, material: PbrMaterial<'a>) -> MaterialId {
+        self.materials.push(material);
+        MaterialId(self.materials.len() - 1)
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    pub fn material_mut(&mut self, id: MaterialId) -> &mut PbrMaterial<'a> {
+        &mut self.materials[id.0]
+    }
+
+    // Moves `id` with its subtree under `parent`, or makes it a root. The local transform
+    // is kept, so the node moves in world space if the new parent's world matrix differs.
+    // Panics without changing the hierarchy if `parent` is `id` or one of its descendants.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        assert!(
+            !parent.is_some_and(|p| self.is_ancestor(id, p)),
+            "a node can't be moved under itself"
+        );
+        match self.nodes[id.0].parent {
+            Some(old) => self.nodes[old.0].children.retain(|c| *c != id),
+            None => self.roots.retain(|c| *c != id),
+        }
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        let node = &mut self.nodes[id.0];
+        node.parent = parent;
+        node.dirty = true;
+    }
+
+    // Whether `ancestor` is `id` or one of its ancestors
+    fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(node) = current {
+            if node == ancestor {
+                return true;
+            }
+            current = self.nodes[node.0].parent;
+        }
+        false
+    }
+
+    // Recomputes the world matrices of dirty nodes and their descendants
+    pub fn update(&mut self) {
+        let mut stack = self
+            .roots
+            .iter()
+            .rev()
+            .map(|id| (*id, Mat4::identity(), false))
+            .collect::<Vec<_>>();
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = &mut self.nodes[id.0];
+            let changed = parent_changed || node.dirty;
+            if changed {
+                node.world = parent_world * node.local.to_mat4();
+                node.dirty = false;
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|c| (*c, world, changed)));
+        }
+    }
+
+    // Lights of all nodes in world space
+    pub fn lights(&self) -> Vec<Light> {
+        self.nodes
+            .iter()
+            .filter_map(|node| node.light.map(|light| light.transformed(&node.world)))
+            .collect()
+    }
+
+    // Updates the world matrices and draws every mesh seen from the camera of the node
+    // `camera` with the PBR shaders. Returns the number of draws, meshes outside of the
+    // view frustum are skipped. Panics if the node has no camera or a zero scale.
+    pub fn render(
+        &mut self,
+        camera: NodeId,
+        state: &RasterizerState,
+        framebuffer: &mut Framebuffer,
+    ) -> usize {
+        let ambient = self.ambient;
+        let sampler = self.sampler;
+        self.render_with(camera, &state.viewport, |draw| {
+            let uniform = PbrUniform {
+                mvp: draw.mvp,
+                model: draw.model,
+                model_normal: draw.model.normal_matrix(),
+                eye: draw.eye,
+                lights: draw.lights,
+                ambient,
+                material: *draw.material,
+                sampler,
+            };
+            render(
+                draw.vertices,
+                &uniform,
+                &PbrVertexShader {},
+                &PbrFragmentShader {},
+                state,
+                framebuffer,
+            );
+        })
+    }
+
+    // Like `render`, but leaves drawing to `draw` so meshes can be rendered with other
+    // shaders and uniforms. `draw` is called for every mesh in the view frustum of the
+    // camera in `viewport`, in the order of the traversal.
+    pub fn render_with(
+        &mut self,
+        camera: NodeId,
+        viewport: &Viewport,
+        mut draw: impl FnMut(&Draw),
+    ) -> usize {
+        self.update();
+
+        let camera_node = &self.nodes[camera.0];
+        let projection = camera_node
+            .camera
+            .expect("the camera node has no camera attached")
+            .to_mat4_for_viewport(viewport);
+        let camera_world = camera_node.world;
+        let view = camera_world
+            .inverse_affine()
+            .expect("the camera node's world transform isn't invertible");
+        let view_projection = projection * view;
+        let eye = camera_world.transform_point(Vec3::zero());
+        let lights = self.lights();
+        let default_material = PbrMaterial {
+            metallic: 0.0,
+            ..PbrMaterial::new(Vec4::new(1.0, 1.0, 1.0, 1.0))
+        };
+
+        let mut draws = 0;
+        let mut stack = self.roots.iter().rev().copied().collect::<Vec<_>>();
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id.0];
+            stack.extend(node.children.iter().rev());
+
+            let mesh = match node.mesh {
+                Some(mesh) => &self.meshes[mesh.0],
+                None => continue,
+            };
+            let model = node.world;
+            let mvp = view_projection * model;
+            if !Culling::new(mesh.bounds, mvp).is_visible() {
+                continue;
+            }
+            draw(&Draw {
+                vertices: &mesh.vertices,
+                model,
+                mvp,
+                eye,
+                lights: &lights,
+                material: node
+                    .material
+                    .map_or(&default_material, |material| &self.materials[material.0]),
+            });
+            draws += 1;
+        }
+        draws
+    }
+
+    // Updates the world matrices and renders every mesh into the shadow map, all meshes
+    // cast shadows
+    pub fn render_shadow_map(&mut self, shadow_map: &mut ShadowMap) {
+        self.update();
+        for node in self.nodes.iter() {
+            if let Some(mesh) = node.mesh {
+                shadow_map.render(&self.meshes[mesh.0].vertices, node.world, &|v: &Vertex| {
+                    v.position
+                });
+            }
+        }
+    }
+}
+
+impl Default for Scene<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::approx::*;
+    use crate::mesh::test::make_quad;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn world_position(scene: &Scene, id: NodeId) -> Vec3 {
+        scene.node(id).world().transform_point(Vec3::zero())
+    }
+
+    #[test]
+    fn test_scene_world_transforms() {
+        let mut scene = Scene::new();
+        let parent = scene.add_node(
+            None,
+            Transform::new(
+                Vec3::new(1.0, 0.0, 0.0),
+                Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), FRAC_PI_2),
+                Vec3::new(2.0, 2.0, 2.0),
+            ),
+        );
+        let child = scene.add_node(
+            Some(parent),
+            Transform::from_translation(Vec3::new(1.0, 0.0, 0.0)),
+        );
+        scene.update();
+        assert!(world_position(&scene, child).approx_eq(&Vec3::new(1.0, 2.0, 0.0)));
+
+        // Changing the parent moves the child
+        scene
+            .node_mut(parent)
+            .set_local(Transform::from_translation(Vec3::new(0.0, 0.0, 3.0)));
+        scene.update();
+        assert!(world_position(&scene, child).approx_eq(&Vec3::new(1.0, 0.0, 3.0)));
+
+        // Reparenting keeps the local transform
+        scene.set_parent(child, None);
+        assert!(scene.node(parent).children().is_empty());
+        scene.update();
+        assert!(world_position(&scene, child).approx_eq(&Vec3::new(1.0, 0.0, 0.0)));
+        assert!(scene.node(child).parent().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "a node can't be moved under itself")]
+    fn test_scene_set_parent_cycle() {
+        let mut scene = Scene::new();
+        let parent = scene.add_node(None, Transform::identity());
+        let child = scene.add_node(Some(parent), Transform::identity());
+        scene.set_parent(parent, Some(child));
+    }
+
+    #[test]
+    fn test_scene_lights() {
+        let mut scene = Scene::new();
+        let node = scene.add_node(None, Transform::from_translation(Vec3::new(0.0, 2.0, 0.0)));
+        scene.node_mut(node).light = Some(Light::Point(PointLight {
+            position: Vec3::new(1.0, 0.0, 0.0),
+            color: Vec3::one(),
+            intensity: 1.0,
+            attenuation: Attenuation::none(),
+        }));
+        scene.update();
+        match scene.lights()[0] {
+            Light::Point(light) => assert!(light.position.approx_eq(&Vec3::new(1.0, 2.0, 0.0))),
+            _ => panic!("expected a point light"),
+        }
+    }
+
+    #[test]
+    fn test_scene_render() {
+        let mut scene = Scene::new();
+        scene.ambient = Vec3::one();
+        let quad = scene.add_mesh(&make_quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]));
+        let material = scene.add_material(PbrMaterial {
+            emissive: Vec3::new(0.0, 0.0, 1.0),
+            ..PbrMaterial::new(Vec4::new(1.0, 0.0, 0.0, 1.0))
+        });
+
+        let camera = scene.add_node(None, Transform::from_translation(Vec3::new(0.0, 0.0, 2.0)));
+        scene.node_mut(camera).camera = Some(Projection::Perspective {
+            fovy: FRAC_PI_2,
+            near: 1.0,
+            far: 10.0,
+        });
+        let visible = scene.add_node(
+            None,
+            Transform::from_translation(Vec3::new(-0.5, -0.5, 0.0)),
+        );
+        scene.node_mut(visible).mesh = Some(quad);
+        scene.node_mut(visible).material = Some(material);
+        // Behind the camera
+        let hidden = scene.add_node(
+            Some(visible),
+            Transform::from_translation(Vec3::new(0.0, 0.0, 5.0)),
+        );
+        scene.node_mut(hidden).mesh = Some(quad);
+
+        let mut framebuffer = Framebuffer::new(8, 8, 1, true);
+        framebuffer.clear_depth(1.0);
+        let state = RasterizerState::for_framebuffer(&framebuffer);
+        assert_eq!(scene.render(camera, &state, &mut framebuffer), 1);
+
+        // The quad covers the center of the viewport
+        let c = framebuffer.color_attachment(0).get_texel(4, 4);
+        assert!(c.e[0] > 0.9);
+        assert!(c.e[2] > 0.9);
+        let c = framebuffer.color_attachment(0).get_texel(0, 0);
+        assert_eq!(c.e[0], 0.0);
+    }
+
+    #[test]
+    fn test_scene_render_shadow_map() {
+        let mut scene = Scene::new();
+        let quad = scene.add_mesh(&make_quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]));
+        // Lies in the xz plane at y = 1
+        let occluder = scene.add_node(
+            None,
+            Transform::new(
+                Vec3::new(-0.5, 1.0, 0.5),
+                Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), -FRAC_PI_2),
+                Vec3::one(),
+            ),
+        );
+        scene.node_mut(occluder).mesh = Some(quad);
+
+        let mut shadow_map = ShadowMap::new(
+            64,
+            ShadowMap::directional_light_view_projection(
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::zero(),
+                4.0,
+            ),
+        );
+        shadow_map.clear();
+        scene.render_shadow_map(&mut shadow_map);
+        assert_eq!(shadow_map.visibility(Vec3::zero()), 0.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(2.0, 0.0, 0.0)), 1.0);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::pipeline::*;
+use crate::texture::*;
+use crate::types::*;
+
+// Depth rendered from the point of view of a light. Render the shadow casters with
+// `render` first, then call `visibility` from the fragment shader of the main pass.
+//
+// See: https://learnopengl.com/Advanced-Lighting/Shadows/Shadow-Mapping
+pub struct ShadowMap {
+    framebuffer: Framebuffer,
+    sampler: Sampler,
+    pub light_view_projection: Mat4,
+    // Used for the depth pass, a slope-scaled bias keeps lit surfaces from shadowing
+    // themselves (shadow acne)
+    pub state: RasterizerState,
+    // Percentage-closer filtering over (2 * pcf_radius + 1)^2 bilinear comparisons,
+    // 0 only takes a single one
+    pub pcf_radius: i32,
+}
+
+impl ShadowMap {
+    pub fn new(size: i32, light_view_projection: Mat4) -> ShadowMap {
+        let framebuffer = Framebuffer::new(size, size, 0, true);
+        let mut state = RasterizerState::for_framebuffer(&framebuffer);
+        state.depth_bias = 16.0;
+        state.slope_scaled_depth_bias = 1.0;
+
+        // Everything outside of the shadow map is lit
+        let mut sampler = Sampler::new(Filter::Linear, WrapMode::ClampToBorder);
+        sampler.border_color = [1.0; 4];
+
+        ShadowMap {
+            framebuffer,
+            sampler,
+            light_view_projection,
+            state,
+            pcf_radius: 1,
+        }
+    }
+
+    // Orthographic light looking along `dir`, covering a sphere of `radius` around
+    // `center`
+    pub fn directional_light_view_projection(dir: Vec3, center: Vec3, radius: f32) -> Mat4 {
+        let dir = dir.normalized();
+        let up = if dir.e[1].abs() > 0.99 {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        };
+        let eye = center - 2.0 * radius * dir;
+        let view = Mat4::look_at(eye, center, up);
+        let projection = Mat4::orthographic(-radius, radius, -radius, radius, radius, 3.0 * radius);
+        projection * view
+    }
+
+    pub fn size(&self) -> i32 {
+        self.framebuffer.width()
+    }
+
+    pub fn depth_texture(&self) -> &Texture {
+        self.framebuffer.depth_attachment().unwrap()
+    }
+
+    pub fn clear(&mut self) {
+        self.framebuffer.clear_depth(1.0);
+    }
+
+    // `position` returns the object space position of a vertex which is transformed by
+    // `model` into world space
+    pub fn render<A>(&mut self, vertices: &[A], model: Mat4, position: &dyn Fn(&A) -> Vec3) {
+        let vs = DepthVertexShader { position };
+        let mvp = self.light_view_projection * model;
+        render(
+            vertices,
+            &mvp,
+            &vs,
+            &DepthFragmentShader {},
+            &self.state,
+            &mut self.framebuffer,
+        );
+    }
+
+    // Fraction of light reaching the world space position `p`, 0.0 is fully shadowed. `p`
+    // goes through the viewport of `state` like the vertices of the depth pass.
+    pub fn visibility(&self, p: Vec3) -> f32 {
+        let ndc = (self.light_view_projection * Vec4::from_vec3(p, 1.0)).perspective_division();
+        let screen = self.state.viewport.ndc_to_screen(ndc);
+        if screen.e[2] > self.state.viewport.max_depth {
+            return 1.0;
+        }
+
+        let texel = 1.0 / self.size() as f32;
+        let u = screen.e[0] * texel;
+        let v = screen.e[1] * texel;
+        let depth = screen.e[2];
+        let mut sum = 0.0;
+        for dy in -self.pcf_radius..=self.pcf_radius {
+            for dx in -self.pcf_radius..=self.pcf_radius {
+                sum += self.sampler.sample_compare(
+                    self.depth_texture(),
+                    u + dx as f32 * texel,
+                    v + dy as f32 * texel,
+                    depth,
+                    CompareFunction::LessEqual,
+                );
+            }
+        }
+        let n = 2 * self.pcf_radius + 1;
+        sum / (n * n) as f32
+    }
+}
+
+struct NoVarying {}
+
+impl Varying for NoVarying {
+    fn as_vec(&self) -> Vec<f32> {
+        Vec::new()
+    }
+
+    fn from_slice(_slice: &[f32]) -> Self {
+        NoVarying {}
+    }
+}
+
+struct DepthVertexShader<'a, A> {
+    position: &'a dyn Fn(&A) -> Vec3,
+}
+
+impl<'a, A> VertexShader<A, Mat4> for DepthVertexShader<'a, A> {
+    type V = NoVarying;
+
+    fn process(&self, attribute: &A, mvp: &Mat4) -> VertexShaderOutput<Self::V> {
+        VertexShaderOutput {
+            pos: *mvp * Vec4::from_vec3((self.position)(attribute), 1.0),
+            varying: NoVarying {},
+        }
+    }
+}
+
+// The shadow map has no color attachments, only the depth is written
+struct DepthFragmentShader {}
+
+impl FragmentShader<NoVarying, Mat4> for DepthFragmentShader {
+    fn process(
+        &self,
+        _input: &FragmentShaderInput<NoVarying>,
+        _uniform: &Mat4,
+    ) -> FragmentShaderOutput {
+        FragmentShaderOutput::depth_only()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A square occluder at y = 1 above a ground plane at y = 0, lit from straight above
+    fn make_shadow_map() -> ShadowMap {
+        let view_projection = ShadowMap::directional_light_view_projection(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            4.0,
+        );
+        let mut shadow_map = ShadowMap::new(64, view_projection);
+        render_occluder(&mut shadow_map);
+        shadow_map
+    }
+
+    fn render_occluder(shadow_map: &mut ShadowMap) {
+        let square = [
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-1.0, 1.0, 1.0),
+        ];
+        shadow_map.render(&square, Mat4::identity(), &|p: &Vec3| *p);
+    }
+
+    #[test]
+    fn test_visibility() {
+        let shadow_map = make_shadow_map();
+        assert_eq!(shadow_map.visibility(Vec3::new(0.0, 0.0, 0.0)), 0.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(2.0, 0.0, 0.0)), 1.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(0.0, 2.0, 0.0)), 1.0);
+        // The occluder doesn't shadow itself
+        assert_eq!(shadow_map.visibility(Vec3::new(0.5, 1.0, 0.5)), 1.0);
+        // Outside of the light's frustum
+        assert_eq!(shadow_map.visibility(Vec3::new(10.0, 0.0, 0.0)), 1.0);
+    }
+
+    #[test]
+    fn test_visibility_depth_range() {
+        let mut shadow_map = make_shadow_map();
+        shadow_map.clear();
+        shadow_map.state.viewport.max_depth = 0.5;
+        render_occluder(&mut shadow_map);
+        assert_eq!(shadow_map.visibility(Vec3::new(0.0, 0.0, 0.0)), 0.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(2.0, 0.0, 0.0)), 1.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(0.5, 1.0, 0.5)), 1.0);
+    }
+
+    #[test]
+    fn test_visibility_pcf() {
+        let mut shadow_map = make_shadow_map();
+        // The edge of the shadow is soft
+        let edge = shadow_map.visibility(Vec3::new(1.0, 0.0, 0.0));
+        assert!(edge > 0.0 && edge < 1.0);
+
+        shadow_map.pcf_radius = 0;
+        let edge = shadow_map.visibility(Vec3::new(1.0, 0.0, 0.0));
+        assert!(edge > 0.0 && edge < 1.0);
+        assert_eq!(shadow_map.visibility(Vec3::new(1.2, 0.0, 0.0)), 1.0);
+    }
+}
@@ -0,0 +1,732 @@
+use crate::color::*;
+use crate::types::*;
+
+// RGBA texture with texels stored as f32. Row 0 is at v = 0, i.e. the origin is at the
+// bottom-left corner as in OpenGL.
+//
+// Level 0 is the base image, levels 1.. are the mipmap chain once generated.
+//
+// Texels are always read and written as linear values, sRGB textures keep their texels
+// encoded and decode them on fetch, before filtering.
+pub struct Texture {
+    levels: Vec<MipLevel>,
+    color_space: ColorSpace,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ColorSpace {
+    Linear,
+    Srgb,
+}
+
+struct MipLevel {
+    width: i32,
+    height: i32,
+    texels: Vec<[f32; 4]>,
+}
+
+impl Texture {
+    pub fn new(width: i32, height: i32) -> Texture {
+        Texture::with_texels(width, height, vec![[0.0; 4]; (width * height) as usize])
+    }
+
+    pub fn with_texels(width: i32, height: i32, texels: Vec<[f32; 4]>) -> Texture {
+        assert!(width > 0 && height > 0);
+        assert_eq!(texels.len(), (width * height) as usize);
+        Texture {
+            levels: vec![MipLevel {
+                width,
+                height,
+                texels,
+            }],
+            color_space: ColorSpace::Linear,
+        }
+    }
+
+    // Same as `from_rgba8` but the color channels are sRGB encoded, which is the case
+    // for most color textures such as diffuse maps
+    pub fn from_srgba8(width: i32, height: i32, data: &[u8]) -> Texture {
+        let mut texture = Texture::from_rgba8(width, height, data);
+        texture.color_space = ColorSpace::Srgb;
+        texture
+    }
+
+    // `data` holds `width * height` RGBA8 texels, starting from the bottom row
+    pub fn from_rgba8(width: i32, height: i32, data: &[u8]) -> Texture {
+        assert_eq!(data.len(), (width * height * 4) as usize);
+        let texels = data
+            .chunks(4)
+            .map(|t| {
+                [
+                    t[0] as f32 / 255.0,
+                    t[1] as f32 / 255.0,
+                    t[2] as f32 / 255.0,
+                    t[3] as f32 / 255.0,
+                ]
+            })
+            .collect();
+        Texture::with_texels(width, height, texels)
+    }
+
+    pub fn width(&self) -> i32 {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.levels[0].height
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level_width(&self, level: usize) -> i32 {
+        self.levels[level].width
+    }
+
+    pub fn level_height(&self, level: usize) -> i32 {
+        self.levels[level].height
+    }
+
+    pub fn get_texel(&self, x: i32, y: i32) -> Vec4 {
+        self.get_level_texel(0, x, y)
+    }
+
+    pub fn get_level_texel(&self, level: usize, x: i32, y: i32) -> Vec4 {
+        let level = &self.levels[level];
+        debug_assert!(x >= 0 && x < level.width);
+        debug_assert!(y >= 0 && y < level.height);
+        let t = level.texels[(y * level.width + x) as usize];
+        let texel = Vec4::new(t[0], t[1], t[2], t[3]);
+        match self.color_space {
+            ColorSpace::Linear => texel,
+            ColorSpace::Srgb => srgb_to_linear_vec4(texel),
+        }
+    }
+
+    // Writes to the base level, mipmaps have to be generated again afterwards
+    pub fn set_texel(&mut self, x: i32, y: i32, texel: Vec4) {
+        let level = &mut self.levels[0];
+        debug_assert!(x >= 0 && x < level.width);
+        debug_assert!(y >= 0 && y < level.height);
+        level.texels[(y * level.width + x) as usize] = match self.color_space {
+            ColorSpace::Linear => texel.e,
+            ColorSpace::Srgb => linear_to_srgb_vec4(texel).e,
+        };
+    }
+
+    // Builds the full mipmap chain down to 1x1 from the base level, replacing any
+    // existing mipmaps. Each level is half the size (rounded down) of the previous one.
+    // Filtering is done in linear space.
+    pub fn generate_mipmaps(&mut self, filter: MipmapFilter) {
+        self.levels.truncate(1);
+        let srgb = self.color_space == ColorSpace::Srgb;
+        loop {
+            let src = self.levels.last().unwrap();
+            if src.width == 1 && src.height == 1 {
+                break;
+            }
+            let width = (src.width / 2).max(1);
+            let height = (src.height / 2).max(1);
+            let level = if srgb {
+                src.map(srgb_to_linear_vec4)
+                    .downsample(width, height, filter)
+                    .map(linear_to_srgb_vec4)
+            } else {
+                src.downsample(width, height, filter)
+            };
+            self.levels.push(level);
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum MipmapFilter {
+    // Averages each 2x2 block, cheap but blurry and prone to aliasing
+    Box,
+    // Lanczos windowed sinc with a = 3, keeps more detail in the smaller levels
+    Lanczos3,
+}
+
+impl MipmapFilter {
+    fn support(self) -> f32 {
+        match self {
+            MipmapFilter::Box => 0.5,
+            MipmapFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(self, t: f32) -> f32 {
+        match self {
+            MipmapFilter::Box => {
+                if t > -0.5 && t <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            MipmapFilter::Lanczos3 => {
+                if t == 0.0 {
+                    1.0
+                } else if t.abs() < 3.0 {
+                    let pt = std::f32::consts::PI * t;
+                    3.0 * pt.sin() * (pt / 3.0).sin() / (pt * pt)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+impl MipLevel {
+    fn map(&self, f: fn(Vec4) -> Vec4) -> MipLevel {
+        MipLevel {
+            width: self.width,
+            height: self.height,
+            texels: self.texels.iter().map(|t| f(Vec4 { e: *t }).e).collect(),
+        }
+    }
+
+    // Separable resampling, texels outside of the level are clamped to the edge
+    fn downsample(&self, width: i32, height: i32, filter: MipmapFilter) -> MipLevel {
+        let horizontal = resample_weights(self.width, width, filter);
+        let mut tmp = vec![[0.0; 4]; (width * self.height) as usize];
+        for y in 0..self.height {
+            for (x, weights) in horizontal.iter().enumerate() {
+                let mut sum = [0.0; 4];
+                for &(sx, w) in weights {
+                    let t = self.texels[(y * self.width + sx) as usize];
+                    for i in 0..4 {
+                        sum[i] += t[i] * w;
+                    }
+                }
+                tmp[(y * width) as usize + x] = sum;
+            }
+        }
+
+        let vertical = resample_weights(self.height, height, filter);
+        let mut texels = vec![[0.0; 4]; (width * height) as usize];
+        for (y, weights) in vertical.iter().enumerate() {
+            for x in 0..width as usize {
+                let mut sum = [0.0; 4];
+                for &(sy, w) in weights {
+                    let t = tmp[(sy * width) as usize + x];
+                    for i in 0..4 {
+                        sum[i] += t[i] * w;
+                    }
+                }
+                texels[y * width as usize + x] = sum;
+            }
+        }
+
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+// For every destination texel returns the source texels it covers and their
+// normalized weights
+fn resample_weights(src: i32, dst: i32, filter: MipmapFilter) -> Vec<Vec<(i32, f32)>> {
+    let scale = src as f32 / dst as f32;
+    let support = filter.support() * scale;
+    (0..dst)
+        .map(|x| {
+            let center = (x as f32 + 0.5) * scale;
+            let first = (center - support).floor() as i32;
+            let last = (center + support).ceil() as i32;
+            let mut weights = (first..=last)
+                .map(|sx| {
+                    let w = filter.weight((sx as f32 + 0.5 - center) / scale);
+                    (sx.max(0).min(src - 1), w)
+                })
+                .filter(|&(_, w)| w != 0.0)
+                .collect::<Vec<_>>();
+            let total: f32 = weights.iter().map(|&(_, w)| w).sum();
+            for (_, w) in weights.iter_mut() {
+                *w /= total;
+            }
+            weights
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+// Used by comparison sampling, e.g. `LessEqual` passes if the reference value is less
+// than or equal to the texel
+#[derive(Copy, Clone, PartialEq)]
+pub enum CompareFunction {
+    Never,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+    Always,
+}
+
+impl CompareFunction {
+    pub fn compare(self, reference: f32, value: f32) -> bool {
+        match self {
+            CompareFunction::Never => false,
+            CompareFunction::Less => reference < value,
+            CompareFunction::LessEqual => reference <= value,
+            CompareFunction::Equal => reference == value,
+            CompareFunction::NotEqual => reference != value,
+            CompareFunction::GreaterEqual => reference >= value,
+            CompareFunction::Greater => reference > value,
+            CompareFunction::Always => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+impl WrapMode {
+    // Maps texel coordinate `i` into [0, n), `None` means the border color should be used
+    fn apply(self, i: i32, n: i32) -> Option<i32> {
+        match self {
+            WrapMode::Repeat => Some(i.rem_euclid(n)),
+            WrapMode::MirroredRepeat => {
+                let m = i.rem_euclid(2 * n);
+                Some(if m < n { m } else { 2 * n - 1 - m })
+            }
+            WrapMode::ClampToEdge => Some(i.max(0).min(n - 1)),
+            WrapMode::ClampToBorder => {
+                if i >= 0 && i < n {
+                    Some(i)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Sampler {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    // Filter between mipmap levels, `None` only samples the base level
+    pub mipmap_filter: Option<Filter>,
+    // Maximum number of samples taken along the axis of anisotropy, 1.0 disables
+    // anisotropic filtering
+    pub max_anisotropy: f32,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+    pub border_color: [f32; 4],
+}
+
+impl Sampler {
+    pub fn new(filter: Filter, wrap: WrapMode) -> Sampler {
+        Sampler {
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: None,
+            max_anisotropy: 1.0,
+            wrap_u: wrap,
+            wrap_v: wrap,
+            border_color: [0.0; 4],
+        }
+    }
+
+    // Bilinear filtering within a level and linear filtering between levels
+    pub fn trilinear(wrap: WrapMode) -> Sampler {
+        Sampler {
+            mipmap_filter: Some(Filter::Linear),
+            ..Sampler::new(Filter::Linear, wrap)
+        }
+    }
+
+    // Samples the base level with the magnification filter
+    pub fn sample(&self, texture: &Texture, u: f32, v: f32) -> Vec4 {
+        self.sample_level(texture, 0, self.mag_filter, u, v)
+    }
+
+    // Samples with an explicit level of detail, lod <= 0 means magnification
+    pub fn sample_lod(&self, texture: &Texture, u: f32, v: f32, lod: f32) -> Vec4 {
+        if lod <= 0.0 {
+            return self.sample_level(texture, 0, self.mag_filter, u, v);
+        }
+
+        let max_level = (texture.levels() - 1) as f32;
+        match self.mipmap_filter {
+            None => self.sample_level(texture, 0, self.min_filter, u, v),
+            Some(Filter::Nearest) => {
+                let level = lod.round().min(max_level) as usize;
+                self.sample_level(texture, level, self.min_filter, u, v)
+            }
+            Some(Filter::Linear) => {
+                let lod = lod.min(max_level);
+                let level0 = lod.floor() as usize;
+                let level1 = lod.ceil() as usize;
+                let t0 = self.sample_level(texture, level0, self.min_filter, u, v);
+                if level0 == level1 {
+                    return t0;
+                }
+                let t1 = self.sample_level(texture, level1, self.min_filter, u, v);
+                lerp4(t0, t1, lod - level0 as f32)
+            }
+        }
+    }
+
+    // Selects the level of detail from the screen-space derivatives of the texture
+    // coordinates, e.g. `FragmentShaderInput::dfdx` and `FragmentShaderInput::dfdy`.
+    //
+    // See: https://www.khronos.org/registry/OpenGL/extensions/EXT/EXT_texture_filter_anisotropic.txt
+    pub fn sample_grad(&self, texture: &Texture, u: f32, v: f32, ddx: Point, ddy: Point) -> Vec4 {
+        let w = texture.width() as f32;
+        let h = texture.height() as f32;
+        let px = ((ddx.x * w).powi(2) + (ddx.y * h).powi(2)).sqrt();
+        let py = ((ddy.x * w).powi(2) + (ddy.y * h).powi(2)).sqrt();
+        let pmax = px.max(py);
+        let pmin = px.min(py);
+
+        if self.max_anisotropy <= 1.0 || pmin <= 0.0 {
+            return self.sample_lod(texture, u, v, pmax.log2());
+        }
+
+        let n = (pmax / pmin)
+            .ceil()
+            .min(self.max_anisotropy.floor())
+            .max(1.0);
+        let lod = (pmax / n).log2();
+        let (du, dv) = if px > py {
+            (ddx.x, ddx.y)
+        } else {
+            (ddy.x, ddy.y)
+        };
+
+        let mut e = [0.0; 4];
+        let count = n as i32;
+        for i in 0..count {
+            // Samples are spread evenly along the major axis, centered at (u, v)
+            let t = (i as f32 + 0.5) / n - 0.5;
+            let s = self.sample_lod(texture, u + du * t, v + dv * t, lod);
+            for (e, s) in e.iter_mut().zip(s.e.iter()) {
+                *e += s / n;
+            }
+        }
+        Vec4 { e }
+    }
+
+    // Compares `reference` against the red channel of the texels of the base level and
+    // filters the results (0.0 or 1.0) instead of the texels, which gives 2x2
+    // percentage-closer filtering with a linear magnification filter. Mostly used with
+    // depth textures.
+    pub fn sample_compare(
+        &self,
+        texture: &Texture,
+        u: f32,
+        v: f32,
+        reference: f32,
+        func: CompareFunction,
+    ) -> f32 {
+        let x = u * texture.width() as f32;
+        let y = v * texture.height() as f32;
+        let test = |x: i32, y: i32| {
+            if func.compare(reference, self.fetch(texture, 0, x, y).e[0]) {
+                1.0
+            } else {
+                0.0
+            }
+        };
+
+        match self.mag_filter {
+            Filter::Nearest => test(x.floor() as i32, y.floor() as i32),
+            Filter::Linear => {
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let x0 = x.floor();
+                let y0 = y.floor();
+                let fx = x - x0;
+                let fy = y - y0;
+                let x0 = x0 as i32;
+                let y0 = y0 as i32;
+
+                let top = test(x0, y0) * (1.0 - fx) + test(x0 + 1, y0) * fx;
+                let bottom = test(x0, y0 + 1) * (1.0 - fx) + test(x0 + 1, y0 + 1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
+    }
+
+    fn sample_level(
+        &self,
+        texture: &Texture,
+        level: usize,
+        filter: Filter,
+        u: f32,
+        v: f32,
+    ) -> Vec4 {
+        let x = u * texture.level_width(level) as f32;
+        let y = v * texture.level_height(level) as f32;
+
+        match filter {
+            Filter::Nearest => self.fetch(texture, level, x.floor() as i32, y.floor() as i32),
+            Filter::Linear => {
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let x0 = x.floor();
+                let y0 = y.floor();
+                let fx = x - x0;
+                let fy = y - y0;
+                let x0 = x0 as i32;
+                let y0 = y0 as i32;
+
+                let t00 = self.fetch(texture, level, x0, y0);
+                let t10 = self.fetch(texture, level, x0 + 1, y0);
+                let t01 = self.fetch(texture, level, x0, y0 + 1);
+                let t11 = self.fetch(texture, level, x0 + 1, y0 + 1);
+
+                lerp4(lerp4(t00, t10, fx), lerp4(t01, t11, fx), fy)
+            }
+        }
+    }
+
+    fn fetch(&self, texture: &Texture, level: usize, x: i32, y: i32) -> Vec4 {
+        match (
+            self.wrap_u.apply(x, texture.level_width(level)),
+            self.wrap_v.apply(y, texture.level_height(level)),
+        ) {
+            (Some(x), Some(y)) => texture.get_level_texel(level, x, y),
+            _ => Vec4 {
+                e: self.border_color,
+            },
+        }
+    }
+}
+
+fn lerp4(a: Vec4, b: Vec4, t: f32) -> Vec4 {
+    let mut e = a.e;
+    for (e, b) in e.iter_mut().zip(b.e.iter()) {
+        *e += (b - *e) * t;
+    }
+    Vec4 { e }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::approx::*;
+
+    // 2x2 texture, red channel holds the texel index
+    fn make_texture() -> Texture {
+        Texture::with_texels(
+            2,
+            2,
+            vec![
+                [0.0, 0.0, 0.0, 1.0],
+                [1.0, 0.0, 0.0, 1.0],
+                [2.0, 0.0, 0.0, 1.0],
+                [3.0, 0.0, 0.0, 1.0],
+            ],
+        )
+    }
+
+    fn assert_sample_red(sampler: &Sampler, texture: &Texture, u: f32, v: f32, r: f32) {
+        assert!(sampler.sample(texture, u, v).e[0].approx_eq(&r));
+    }
+
+    #[test]
+    fn test_wrap_mode() {
+        assert_eq!(WrapMode::Repeat.apply(-1, 4), Some(3));
+        assert_eq!(WrapMode::Repeat.apply(5, 4), Some(1));
+        assert_eq!(WrapMode::MirroredRepeat.apply(4, 4), Some(3));
+        assert_eq!(WrapMode::MirroredRepeat.apply(-1, 4), Some(0));
+        assert_eq!(WrapMode::MirroredRepeat.apply(9, 4), Some(1));
+        assert_eq!(WrapMode::ClampToEdge.apply(-3, 4), Some(0));
+        assert_eq!(WrapMode::ClampToEdge.apply(7, 4), Some(3));
+        assert_eq!(WrapMode::ClampToBorder.apply(-1, 4), None);
+        assert_eq!(WrapMode::ClampToBorder.apply(4, 4), None);
+        assert_eq!(WrapMode::ClampToBorder.apply(2, 4), Some(2));
+    }
+
+    #[test]
+    fn test_sample_nearest() {
+        let texture = make_texture();
+        let sampler = Sampler::new(Filter::Nearest, WrapMode::Repeat);
+        assert_sample_red(&sampler, &texture, 0.25, 0.25, 0.0);
+        assert_sample_red(&sampler, &texture, 0.75, 0.25, 1.0);
+        assert_sample_red(&sampler, &texture, 0.25, 0.75, 2.0);
+        assert_sample_red(&sampler, &texture, 1.75, -0.25, 3.0);
+    }
+
+    #[test]
+    fn test_sample_linear() {
+        let texture = make_texture();
+        let sampler = Sampler::new(Filter::Linear, WrapMode::ClampToEdge);
+        assert_sample_red(&sampler, &texture, 0.25, 0.25, 0.0);
+        assert_sample_red(&sampler, &texture, 0.5, 0.25, 0.5);
+        assert_sample_red(&sampler, &texture, 0.5, 0.5, 1.5);
+        assert_sample_red(&sampler, &texture, 0.0, 0.0, 0.0);
+
+        let sampler = Sampler::new(Filter::Linear, WrapMode::Repeat);
+        assert_sample_red(&sampler, &texture, 0.0, 0.25, 0.5);
+    }
+
+    #[test]
+    fn test_generate_mipmaps() {
+        let mut texture = Texture::new(8, 3);
+        texture.generate_mipmaps(MipmapFilter::Box);
+        let sizes = (0..texture.levels())
+            .map(|l| (texture.level_width(l), texture.level_height(l)))
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(8, 3), (4, 1), (2, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_generate_mipmaps_box() {
+        let mut texture = make_texture();
+        texture.generate_mipmaps(MipmapFilter::Box);
+        assert_eq!(texture.levels(), 2);
+        assert!(texture
+            .get_level_texel(1, 0, 0)
+            .approx_eq(&Vec4::new(1.5, 0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn test_generate_mipmaps_lanczos3() {
+        // A constant image must stay constant in every level
+        let mut texture = Texture::with_texels(16, 16, vec![[0.25, 0.5, 0.75, 1.0]; 256]);
+        texture.generate_mipmaps(MipmapFilter::Lanczos3);
+        assert_eq!(texture.levels(), 5);
+        for level in 0..texture.levels() {
+            assert!(texture
+                .get_level_texel(level, 0, 0)
+                .approx_eq(&Vec4::new(0.25, 0.5, 0.75, 1.0)));
+        }
+    }
+
+    #[test]
+    fn test_sample_lod() {
+        let mut texture = make_texture();
+        texture.generate_mipmaps(MipmapFilter::Box);
+
+        let mut sampler = Sampler::new(Filter::Nearest, WrapMode::Repeat);
+        assert!(sampler.sample_lod(&texture, 0.25, 0.25, 1.0).e[0].approx_eq(&0.0));
+
+        sampler.mipmap_filter = Some(Filter::Nearest);
+        assert!(sampler.sample_lod(&texture, 0.25, 0.25, 0.4).e[0].approx_eq(&0.0));
+        assert!(sampler.sample_lod(&texture, 0.25, 0.25, 0.6).e[0].approx_eq(&1.5));
+        assert!(sampler.sample_lod(&texture, 0.25, 0.25, 5.0).e[0].approx_eq(&1.5));
+
+        sampler.mipmap_filter = Some(Filter::Linear);
+        assert!(sampler.sample_lod(&texture, 0.25, 0.25, 0.5).e[0].approx_eq(&0.75));
+    }
+
+    #[test]
+    fn test_sample_grad() {
+        let mut texture = make_texture();
+        texture.generate_mipmaps(MipmapFilter::Box);
+        let sampler = Sampler::trilinear(WrapMode::Repeat);
+
+        // One texel per pixel, no minification
+        let c = sampler.sample_grad(
+            &texture,
+            0.25,
+            0.25,
+            Point::new(0.5, 0.0),
+            Point::new(0.0, 0.5),
+        );
+        assert!(c.e[0].approx_eq(&0.0));
+
+        // Two texels per pixel, fully in level 1
+        let c = sampler.sample_grad(
+            &texture,
+            0.25,
+            0.25,
+            Point::new(1.0, 0.0),
+            Point::new(0.0, 1.0),
+        );
+        assert!(c.e[0].approx_eq(&1.5));
+    }
+
+    #[test]
+    fn test_sample_grad_anisotropic() {
+        // Rows alternate between 0 and 1
+        let texels = (0..64)
+            .map(|i| [((i / 8) % 2) as f32, 0.0, 0.0, 1.0])
+            .collect::<Vec<_>>();
+        let mut texture = Texture::with_texels(8, 8, texels);
+        texture.generate_mipmaps(MipmapFilter::Box);
+
+        // Footprint is 2 texels long along u and 1 texel along v
+        let ddx = Point::new(0.25, 0.0);
+        let ddy = Point::new(0.0, 0.125);
+        let v = 4.5 / 8.0;
+
+        // Isotropic filtering picks level 1 and blurs the rows together
+        let mut sampler = Sampler::trilinear(WrapMode::Repeat);
+        assert!(sampler.sample_grad(&texture, 0.5, v, ddx, ddy).e[0].approx_eq(&0.5));
+
+        // Anisotropic filtering takes two samples along u from level 0
+        sampler.max_anisotropy = 8.0;
+        assert!(sampler.sample_grad(&texture, 0.5, v, ddx, ddy).e[0].approx_eq(&0.0));
+    }
+
+    #[test]
+    fn test_srgb_texture() {
+        let mut texture = Texture::from_srgba8(2, 1, &[0, 128, 255, 255, 255, 255, 255, 0]);
+        assert!(texture.color_space() == ColorSpace::Srgb);
+        assert!(texture
+            .get_texel(0, 0)
+            .approx_eq(&Vec4::new(0.0, 0.215_861, 1.0, 1.0)));
+
+        // Filtering happens after decoding
+        let sampler = Sampler::new(Filter::Linear, WrapMode::ClampToEdge);
+        assert!(sampler.sample(&texture, 0.5, 0.5).e[0].approx_eq(&0.5));
+
+        texture.generate_mipmaps(MipmapFilter::Box);
+        assert!(texture
+            .get_level_texel(1, 0, 0)
+            .approx_eq(&Vec4::new(0.5, 0.607_931, 1.0, 0.5)));
+
+        texture.set_texel(0, 0, Vec4::new(0.5, 0.5, 0.5, 0.5));
+        assert!(texture
+            .get_texel(0, 0)
+            .approx_eq(&Vec4::new(0.5, 0.5, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn test_sample_compare() {
+        let texture = make_texture();
+        let sampler = Sampler::new(Filter::Nearest, WrapMode::ClampToEdge);
+        let func = CompareFunction::LessEqual;
+        assert_eq!(sampler.sample_compare(&texture, 0.75, 0.25, 1.0, func), 1.0);
+        assert_eq!(sampler.sample_compare(&texture, 0.75, 0.25, 1.5, func), 0.0);
+
+        // Results are filtered, not the texels
+        let sampler = Sampler::new(Filter::Linear, WrapMode::ClampToEdge);
+        assert_eq!(sampler.sample_compare(&texture, 0.5, 0.5, 1.5, func), 0.5);
+        assert_eq!(sampler.sample_compare(&texture, 0.5, 0.25, 0.5, func), 0.5);
+    }
+
+    #[test]
+    fn test_sample_border() {
+        let texture = make_texture();
+        let mut sampler = Sampler::new(Filter::Nearest, WrapMode::ClampToBorder);
+        sampler.border_color = [9.0, 0.0, 0.0, 0.0];
+        assert_sample_red(&sampler, &texture, 1.25, 0.25, 9.0);
+        assert_sample_red(&sampler, &texture, 0.25, 0.25, 0.0);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::types::*;
+
+// Columns of a `Mat4` which are less orthogonal than this (cosine of the angle between
+// them) are considered sheared
+const SHEAR_TOLERANCE: f64 = 1e-4;
+
+// Translation, rotation and scale, applied to points in the order scale, rotation,
+// translation, i.e. the matrix is `T * R * S`.
+//
+// A composition of transforms with non-uniform scale and rotation can contain shear, which
+// this type can't represent. `Mul` and `inverse` drop the shear in that case, like most
+// engines do for scene graphs; compose the matrices instead when it matters.
+#[derive(Copy, Clone)]
+pub struct Transformation<T: Float> {
+    pub translation: Vector3<T>,
+    pub rotation: Quaternion<T>,
+    pub scale: Vector3<T>,
+}
+
+pub type Transform = Transformation<f32>;
+pub type DTransform = Transformation<f64>;
+
+impl<T: Float> Transformation<T> {
+    pub fn new(
+        translation: Vector3<T>,
+        rotation: Quaternion<T>,
+        scale: Vector3<T>,
+    ) -> Transformation<T> {
+        Transformation {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn identity() -> Transformation<T> {
+        Transformation::new(Vector3::zero(), Quaternion::identity(), Vector3::one())
+    }
+
+    pub fn from_translation(translation: Vector3<T>) -> Transformation<T> {
+        Transformation {
+            translation,
+            ..Transformation::identity()
+        }
+    }
+
+    pub fn from_rotation(rotation: Quaternion<T>) -> Transformation<T> {
+        Transformation {
+            rotation,
+            ..Transformation::identity()
+        }
+    }
+
+    pub fn from_scale(scale: Vector3<T>) -> Transformation<T> {
+        Transformation {
+            scale,
+            ..Transformation::identity()
+        }
+    }
+
+    pub fn to_mat4(&self) -> Matrix4<T> {
+        Matrix4::translate(self.translation) * self.rotation.to_mat4() * Matrix4::scale(self.scale)
+    }
+
+    // Decomposes an affine matrix, returns `None` if it has a projective part, a zero
+    // scale or shear. A mirroring matrix gets a negative x scale.
+    //
+    // See: https://caff.de/posts/4X4-matrix-decomposition/decomposition.pdf
+    pub fn from_mat4(m: &Matrix4<T>) -> Option<Transformation<T>> {
+        if m.e[12] != T::ZERO || m.e[13] != T::ZERO || m.e[14] != T::ZERO || m.e[15] != T::ONE {
+            return None;
+        }
+        let linear = Matrix3::from_mat4(m);
+        if has_shear(&linear) {
+            return None;
+        }
+
+        let mut scale = Vector3::new(
+            linear.col(0).len(),
+            linear.col(1).len(),
+            linear.col(2).len(),
+        );
+        if scale.e.iter().any(|s| *s <= T::EPSILON) {
+            return None;
+        }
+        if linear.determinant() < T::ZERO {
+            scale.e[0] = -scale.e[0];
+        }
+
+        let x = linear.col(0) / scale.e[0];
+        let y = linear.col(1) / scale.e[1];
+        let z = linear.col(2) / scale.e[2];
+        let rotation = Quaternion::from_mat4(&Matrix4::from_basis(x, y, z)).normalized();
+
+        Some(Transformation {
+            translation: Vector3::new(m.e[3], m.e[7], m.e[11]),
+            rotation,
+            scale,
+        })
+    }
+
+    pub fn transform_point(&self, p: Vector3<T>) -> Vector3<T> {
+        self.rotation.rotate(self.scale.mul_elements(p)) + self.translation
+    }
+
+    pub fn transform_vector(&self, v: Vector3<T>) -> Vector3<T> {
+        self.rotation.rotate(self.scale.mul_elements(v))
+    }
+
+    // Interpolates the components separately, with `slerp` for the rotation
+    pub fn lerp(&self, other: &Transformation<T>, t: T) -> Transformation<T> {
+        Transformation {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+
+    // Exact if the scale is uniform or there is no rotation, see `Transformation`. Returns
+    // `None` if a component of the scale is 0.
+    pub fn inverse(&self) -> Option<Transformation<T>> {
+        if self.scale.e.contains(&T::ZERO) {
+            return None;
+        }
+        let rotation = self.rotation.inverse();
+        let scale = self.scale.map(|s| T::ONE / s);
+        Some(Transformation {
+            translation: -scale.mul_elements(rotation.rotate(self.translation)),
+            rotation,
+            scale,
+        })
+    }
+}
+
+// Whether the columns of the linear part `m` aren't orthogonal
+pub fn has_shear<T: Float>(m: &Matrix3<T>) -> bool {
+    let c = [m.col(0), m.col(1), m.col(2)];
+    for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+        let len = c[i].len() * c[j].len();
+        if len > T::ZERO && (c[i] * c[j]).abs() > T::from_f64(SHEAR_TOLERANCE) * len {
+            return true;
+        }
+    }
+    false
+}
+
+// `a * b` applies `b` first and then `a`, like for matrices. Exact if `a` has a uniform
+// scale, see `Transformation`.
+impl<T: Float> std::ops::Mul for Transformation<T> {
+    type Output = Transformation<T>;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Transformation {
+            translation: self.transform_point(rhs.translation),
+            rotation: self.rotation * rhs.rotation,
+            scale: self.scale.mul_elements(rhs.scale),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::approx::*;
+
+    fn make_transform() -> Transform {
+        Transform::new(
+            Vec3::new(1.0, -2.0, 3.0),
+            Quat::from_euler(0.3, -1.2, 2.5),
+            Vec3::new(2.0, 0.5, 3.0),
+        )
+    }
+
+    #[test]
+    fn test_transform_to_mat4() {
+        let transform = make_transform();
+        let m = transform.to_mat4();
+        let p = Vec3::new(0.5, 1.0, -1.5);
+        assert!(transform
+            .transform_point(p)
+            .approx_eq(&m.transform_point(p)));
+        assert!(transform
+            .transform_vector(p)
+            .approx_eq(&m.transform_vector(p)));
+    }
+
+    #[test]
+    fn test_transform_from_mat4() {
+        let transform = make_transform();
+        let decomposed = Transform::from_mat4(&transform.to_mat4()).unwrap();
+        assert!(decomposed.translation.approx_eq(&transform.translation));
+        assert!(decomposed.scale.approx_eq(&transform.scale));
+        assert!((decomposed.rotation.dot(transform.rotation).abs() - 1.0).abs() < 0.0001);
+
+        let mirrored = Transform {
+            scale: Vec3::new(-1.0, 2.0, 1.0),
+            ..transform
+        };
+        let decomposed = Transform::from_mat4(&mirrored.to_mat4()).unwrap();
+        assert!(decomposed.to_mat4().approx_eq(&mirrored.to_mat4()));
+        assert!(decomposed.scale.e[0] < 0.0);
+    }
+
+    #[test]
+    fn test_transform_shear() {
+        // Non-uniform scale after a rotation
+        let m = Mat4::scale(Vec3::new(1.0, 3.0, 1.0)) * Mat4::rotate_z(0.5);
+        assert!(has_shear(&Mat3::from_mat4(&m)));
+        assert!(Transform::from_mat4(&m).is_none());
+
+        assert!(!has_shear(&Mat3::from_mat4(&make_transform().to_mat4())));
+        assert!(Transform::from_mat4(&Mat4::scale(Vec3::new(1.0, 0.0, 1.0))).is_none());
+        assert!(Transform::from_mat4(&Mat4::perspective(1.0, 1.0, 1.0, 2.0)).is_none());
+    }
+
+    #[test]
+    fn test_transform_compose() {
+        let parent = Transform::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.8),
+            Vec3::new(2.0, 2.0, 2.0),
+        );
+        let child = make_transform();
+        assert!((parent * child)
+            .to_mat4()
+            .approx_eq(&(parent.to_mat4() * child.to_mat4())));
+    }
+
+    #[test]
+    fn test_transform_inverse() {
+        let transform = Transform {
+            scale: Vec3::new(3.0, 3.0, 3.0),
+            ..make_transform()
+        };
+        assert!(transform
+            .inverse()
+            .unwrap()
+            .to_mat4()
+            .approx_eq(&transform.to_mat4().inverse().unwrap()));
+        assert!((transform * transform.inverse().unwrap())
+            .to_mat4()
+            .approx_eq(&Mat4::identity()));
+
+        // Non-uniform scale without rotation
+        let transform = Transform {
+            rotation: Quat::identity(),
+            ..make_transform()
+        };
+        assert!(transform
+            .inverse()
+            .unwrap()
+            .to_mat4()
+            .approx_eq(&transform.to_mat4().inverse().unwrap()));
+
+        assert!(Transform::from_scale(Vec3::new(1.0, 0.0, 1.0))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn test_transform_f64() {
+        // Far from the origin f32 can't resolve millimeters anymore
+        let transform = DTransform::from_translation(DVec3::new(1.0e9, 0.0, 0.0));
+        let p = transform
+            .inverse()
+            .unwrap()
+            .transform_point(DVec3::new(1.0e9 + 0.001, 0.0, 0.0));
+        assert!((p.e[0] - 0.001).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_transform_lerp() {
+        let a = Transform::from_translation(Vec3::new(2.0, 0.0, 0.0));
+        let b = Transform::new(
+            Vec3::new(4.0, 2.0, 0.0),
+            Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 1.0),
+            Vec3::new(3.0, 3.0, 3.0),
+        );
+        let c = a.lerp(&b, 0.5);
+        assert!(c.translation.approx_eq(&Vec3::new(3.0, 1.0, 0.0)));
+        assert!(c.scale.approx_eq(&Vec3::new(2.0, 2.0, 2.0)));
+        assert!(c
+            .transform_vector(Vec3::new(1.0, 0.0, 0.0))
+            .approx_eq(&(2.0 * Vec3::new(0.5f32.cos(), 0.5f32.sin(), 0.0))));
+    }
+}
@@ -146,6 +146,7 @@ pub struct TriangleRasterizedPixel {
     pub b2: f32,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn fill_triangle_iter(
     ax: f32,
     ay: f32,
@@ -169,6 +170,215 @@ pub fn fill_triangle_iter(
     )
 }
 
+// Same as `FillTriangleIter` but walks the bounding box in 2x2 pixel quads, which is
+// what GPUs do to compute screen-space derivatives. Every quad that touches the triangle
+// is emitted with all of its four pixels. Pixels outside the triangle (helper pixels)
+// have their coverage bit cleared but still carry the (extrapolated) barycentric
+// coordinates so varyings can be differentiated across the quad.
+pub struct FillQuadIter {
+    minx: i32,
+    miny: i32,
+    maxx: i32,
+    maxy: i32,
+    qminx: i32,
+    qx: i32,
+    qy: i32,
+    a01: f32,
+    a12: f32,
+    a20: f32,
+    b01: f32,
+    b12: f32,
+    b20: f32,
+    w0_row: f32,
+    w1_row: f32,
+    w2_row: f32,
+    w0: f32,
+    w1: f32,
+    w2: f32,
+    b0: bool,
+    b1: bool,
+    b2: bool,
+    area2: f32,
+}
+
+impl FillQuadIter {
+    pub fn new(v0: Point, mut v1: Point, mut v2: Point, clip: Rect) -> FillQuadIter {
+        let mut area2 = signed_area(v0, v1, v2);
+        if area2 < 0.0 {
+            std::mem::swap(&mut v1, &mut v2);
+            area2 = -area2;
+        }
+
+        debug_assert!(signed_area(v0, v1, v2) >= 0.0);
+
+        let minx = v0.x.min(v1.x).min(v2.x).floor().max(clip.min.x) as i32;
+        let miny = v0.y.min(v1.y).min(v2.y).floor().max(clip.min.y) as i32;
+        let maxx = (v0.x.max(v1.x).max(v2.x).ceil().min(clip.max.x) as i32).max(minx);
+        let maxy = (v0.y.max(v1.y).max(v2.y).ceil().min(clip.max.y) as i32).max(miny);
+
+        // Quads are aligned to even pixel coordinates
+        let qx = minx & !1;
+        let qy = miny & !1;
+        let p = Point::new(qx as f32 + 0.5, qy as f32 + 0.5);
+        let w0 = signed_area(v1, v2, p);
+        let w1 = signed_area(v2, v0, p);
+        let w2 = signed_area(v0, v1, p);
+
+        FillQuadIter {
+            minx,
+            miny,
+            maxx,
+            maxy,
+            qminx: qx,
+            qx,
+            qy,
+            a01: v0.y - v1.y,
+            a12: v1.y - v2.y,
+            a20: v2.y - v0.y,
+            b01: v1.x - v0.x,
+            b12: v2.x - v1.x,
+            b20: v0.x - v2.x,
+            w0_row: w0,
+            w1_row: w1,
+            w2_row: w2,
+            w0,
+            w1,
+            w2,
+            b0: is_top_left(v1, v2),
+            b1: is_top_left(v2, v0),
+            b2: is_top_left(v0, v1),
+            area2,
+        }
+    }
+}
+
+impl Iterator for FillQuadIter {
+    type Item = TriangleRasterizedQuad;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.qx >= self.maxx {
+                self.qy += 2;
+                self.w0_row += 2.0 * self.b12;
+                self.w1_row += 2.0 * self.b20;
+                self.w2_row += 2.0 * self.b01;
+
+                self.qx = self.qminx;
+                self.w0 = self.w0_row;
+                self.w1 = self.w1_row;
+                self.w2 = self.w2_row;
+            }
+
+            if self.qx >= self.maxx || self.qy >= self.maxy {
+                return None;
+            }
+
+            let mut quad = TriangleRasterizedQuad {
+                x: self.qx,
+                y: self.qy,
+                mask: 0,
+                pixels: [TriangleRasterizedPixel {
+                    x: 0,
+                    y: 0,
+                    aa: 1.0,
+                    b0: 0.0,
+                    b1: 0.0,
+                    b2: 0.0,
+                }; 4],
+            };
+
+            for (i, pixel) in quad.pixels.iter_mut().enumerate() {
+                let dx = (i & 1) as i32;
+                let dy = (i >> 1) as i32;
+                let x = self.qx + dx;
+                let y = self.qy + dy;
+                let w0 = self.w0 + dx as f32 * self.a12 + dy as f32 * self.b12;
+                let w1 = self.w1 + dx as f32 * self.a20 + dy as f32 * self.b20;
+                let w2 = self.w2 + dx as f32 * self.a01 + dy as f32 * self.b01;
+
+                if x >= self.minx
+                    && x < self.maxx
+                    && y >= self.miny
+                    && y < self.maxy
+                    && (w0 > 0.0 || self.b0 && w0 == 0.0)
+                    && (w1 > 0.0 || self.b1 && w1 == 0.0)
+                    && (w2 > 0.0 || self.b2 && w2 == 0.0)
+                {
+                    quad.mask |= 1 << i;
+                }
+
+                *pixel = TriangleRasterizedPixel {
+                    x,
+                    y,
+                    aa: 1.0,
+                    b0: w0 / self.area2,
+                    b1: w1 / self.area2,
+                    b2: w2 / self.area2,
+                };
+            }
+
+            self.qx += 2;
+            self.w0 += 2.0 * self.a12;
+            self.w1 += 2.0 * self.a20;
+            self.w2 += 2.0 * self.a01;
+
+            if quad.mask != 0 {
+                return Some(quad);
+            }
+        }
+    }
+}
+
+// Pixels are stored in the order top-left, top-right, bottom-left, bottom-right,
+// i.e. pixel `i` is at (x + (i & 1), y + (i >> 1)).
+#[derive(Copy, Clone)]
+pub struct TriangleRasterizedQuad {
+    pub x: i32,
+    pub y: i32,
+    pub mask: u8,
+    pub pixels: [TriangleRasterizedPixel; 4],
+}
+
+impl TriangleRasterizedQuad {
+    pub fn is_covered(&self, i: usize) -> bool {
+        self.mask & (1 << i) != 0
+    }
+
+    // Coarse derivatives of an interpolated attribute, `f` holds the attribute value
+    // of each pixel in the quad.
+    pub fn dfdx(f: [f32; 4]) -> f32 {
+        f[1] - f[0]
+    }
+
+    pub fn dfdy(f: [f32; 4]) -> f32 {
+        f[2] - f[0]
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn fill_triangle_quad_iter(
+    ax: f32,
+    ay: f32,
+    bx: f32,
+    by: f32,
+    cx: f32,
+    cy: f32,
+    minx: i32,
+    miny: i32,
+    maxx: i32,
+    maxy: i32,
+) -> FillQuadIter {
+    FillQuadIter::new(
+        Point::new(ax, ay),
+        Point::new(bx, by),
+        Point::new(cx, cy),
+        Rect::new(
+            Point::new(minx as f32, miny as f32),
+            Point::new(maxx as f32, maxy as f32),
+        ),
+    )
+}
+
 fn signed_area(a: Point, b: Point, c: Point) -> f32 {
     (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
 }
@@ -227,11 +437,81 @@ mod test {
         let output = fill_triangle_iter(
             points[0], points[1], points[2], points[3], points[4], points[5], 0, 0, WIDTH, HEIGHT,
         )
-        .flat_map(|p| PixelCoordIter::new(p))
+        .flat_map(PixelCoordIter::new)
         .collect::<Vec<_>>();
         assert_eq!(output, pixels);
     }
 
+    fn assert_quads_cover_same_pixels(points: [f32; 6]) {
+        let mut expected = fill_triangle_iter(
+            points[0], points[1], points[2], points[3], points[4], points[5], 0, 0, WIDTH, HEIGHT,
+        )
+        .map(|p| (p.x, p.y))
+        .collect::<Vec<_>>();
+        expected.sort();
+
+        let mut output = Vec::new();
+        for quad in fill_triangle_quad_iter(
+            points[0], points[1], points[2], points[3], points[4], points[5], 0, 0, WIDTH, HEIGHT,
+        ) {
+            assert_eq!(quad.x % 2, 0);
+            assert_eq!(quad.y % 2, 0);
+            for (i, p) in quad.pixels.iter().enumerate() {
+                assert_eq!(p.x, quad.x + (i & 1) as i32);
+                assert_eq!(p.y, quad.y + (i >> 1) as i32);
+                if quad.is_covered(i) {
+                    output.push((p.x, p.y));
+                }
+            }
+        }
+        output.sort();
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_quad_coverage() {
+        assert_quads_cover_same_pixels([4.5, 7.5, 4.5, 7.5, 4.5, 7.5]);
+        assert_quads_cover_same_pixels([1.0, 2.0, 5.0, 2.0, 7.0, 4.0]);
+        assert_quads_cover_same_pixels([5.0, 2.0, 8.0, 1.0, 7.0, 4.0]);
+        assert_quads_cover_same_pixels([9.5, 0.5, 9.5, -1.5, 10.5, 0.5]);
+        assert_quads_cover_same_pixels([12.5, 2.5, 12.5, 0.5, 14.5, 2.5]);
+        assert_quads_cover_same_pixels([1.0, 7.0, 2.0, 4.0, 6.0, 6.0]);
+        assert_quads_cover_same_pixels([7.8, 5.5, 9.5, 2.8, 11.8, 5.5]);
+        assert_quads_cover_same_pixels([13.5, 6.5, 14.5, 5.5, 15.0, 8.0]);
+        assert_quads_cover_same_pixels([3.0, 1.0, 97.5, 20.0, 50.0, 99.5]);
+    }
+
+    #[test]
+    fn test_quad_clip() {
+        for quad in fill_triangle_quad_iter(-5.0, -5.0, 20.0, -5.0, -5.0, 20.0, 1, 1, 7, 7) {
+            for (i, p) in quad.pixels.iter().enumerate() {
+                if quad.is_covered(i) {
+                    assert!(p.x >= 1 && p.x < 7 && p.y >= 1 && p.y < 7);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_quad_derivatives() {
+        // Attribute f(x, y) = 3x - 2y + 1 assigned to the vertices
+        let v = [(1.0, 1.0), (30.0, 3.0), (10.0, 25.0)];
+        let f = |x: f32, y: f32| 3.0 * x - 2.0 * y + 1.0;
+        let attr = Vec3::new(f(v[0].0, v[0].1), f(v[1].0, v[1].1), f(v[2].0, v[2].1));
+
+        for quad in fill_triangle_quad_iter(
+            v[0].0, v[0].1, v[1].0, v[1].1, v[2].0, v[2].1, 0, 0, WIDTH, HEIGHT,
+        ) {
+            let mut values = [0.0; 4];
+            for (i, p) in quad.pixels.iter().enumerate() {
+                values[i] = attr * Vec3::new(p.b0, p.b1, p.b2);
+            }
+            assert!((TriangleRasterizedQuad::dfdx(values) - 3.0).abs() < 0.001);
+            assert!((TriangleRasterizedQuad::dfdy(values) + 2.0).abs() < 0.001);
+        }
+    }
+
     #[test]
     fn test_fill_rule1() {
         let points = [4.5, 7.5, 4.5, 7.5, 4.5, 7.5];
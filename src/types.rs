@@ -1,4 +1,142 @@
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
+};
+
+// Scalar type of the math types. Rendering uses the `f32` aliases (`Vec3`, `Mat4`, ...),
+// the `f64` ones (`DVec3`, `DMat4`, ...) keep precision for large world-space
+// coordinates and are converted with `cast` before rasterization.
+pub trait Float:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const TWO: Self;
+    const HALF: Self;
+    const EPSILON: Self;
+
+    fn from_f64(v: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn tan(self) -> Self;
+    fn acos(self) -> Self;
+    fn atan2(self, other: Self) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn is_nan(self) -> bool;
+    fn is_sign_negative(self) -> bool;
+    // Number of representable values between two values of the same sign
+    fn ulps_between(self, other: Self) -> u64;
+}
+
+macro_rules! impl_float {
+    ($t:ident) => {
+        impl Float for $t {
+            const ZERO: $t = 0.0;
+            const ONE: $t = 1.0;
+            const TWO: $t = 2.0;
+            const HALF: $t = 0.5;
+            const EPSILON: $t = $t::EPSILON;
+
+            fn from_f64(v: f64) -> $t {
+                v as $t
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn sqrt(self) -> $t {
+                $t::sqrt(self)
+            }
+
+            fn abs(self) -> $t {
+                $t::abs(self)
+            }
+
+            fn sin(self) -> $t {
+                $t::sin(self)
+            }
+
+            fn cos(self) -> $t {
+                $t::cos(self)
+            }
+
+            fn tan(self) -> $t {
+                $t::tan(self)
+            }
+
+            fn acos(self) -> $t {
+                $t::acos(self)
+            }
+
+            fn atan2(self, other: $t) -> $t {
+                $t::atan2(self, other)
+            }
+
+            fn powf(self, n: $t) -> $t {
+                $t::powf(self, n)
+            }
+
+            fn min(self, other: $t) -> $t {
+                $t::min(self, other)
+            }
+
+            fn max(self, other: $t) -> $t {
+                $t::max(self, other)
+            }
+
+            fn is_nan(self) -> bool {
+                $t::is_nan(self)
+            }
+
+            fn is_sign_negative(self) -> bool {
+                $t::is_sign_negative(self)
+            }
+
+            fn ulps_between(self, other: $t) -> u64 {
+                (self.to_bits() as i128 - other.to_bits() as i128).unsigned_abs() as u64
+            }
+        }
+    };
+}
+
+impl_float!(f32);
+impl_float!(f64);
+
+pub type Point = Point2<f32>;
+pub type DPoint = Point2<f64>;
+pub type Rect = Rect2<f32>;
+pub type DRect = Rect2<f64>;
+pub type Vec2 = Vector2<f32>;
+pub type DVec2 = Vector2<f64>;
+pub type Vec3 = Vector3<f32>;
+pub type DVec3 = Vector3<f64>;
+pub type Vec4 = Vector4<f32>;
+pub type DVec4 = Vector4<f64>;
+pub type Mat3 = Matrix3<f32>;
+pub type DMat3 = Matrix3<f64>;
+pub type Mat4 = Matrix4<f32>;
+pub type DMat4 = Matrix4<f64>;
+pub type Quat = Quaternion<f32>;
+pub type DQuat = Quaternion<f64>;
+
+fn cast_array<T: Float, U: Float, const N: usize>(e: [T; N]) -> [U; N] {
+    e.map(|v| U::from_f64(v.to_f64()))
+}
 
 #[derive(Copy, Clone)]
 pub struct Pixel {
@@ -8,194 +146,457 @@ pub struct Pixel {
 }
 
 #[derive(Copy, Clone)]
-pub struct Point {
-    pub x: f32,
-    pub y: f32,
+pub struct Point2<T> {
+    pub x: T,
+    pub y: T,
+}
+
+impl<T: Float> Point2<T> {
+    pub fn new(x: T, y: T) -> Point2<T> {
+        Point2 { x, y }
+    }
+
+    pub fn cast<U: Float>(&self) -> Point2<U> {
+        Point2::new(U::from_f64(self.x.to_f64()), U::from_f64(self.y.to_f64()))
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Rect2<T> {
+    pub min: Point2<T>,
+    pub max: Point2<T>,
 }
 
-impl Point {
-    pub fn new(x: f32, y: f32) -> Point {
-        Point { x, y }
+impl<T: Float> Rect2<T> {
+    pub fn new(min: Point2<T>, max: Point2<T>) -> Rect2<T> {
+        Rect2 { min, max }
+    }
+
+    pub fn cast<U: Float>(&self) -> Rect2<U> {
+        Rect2::new(self.min.cast(), self.max.cast())
     }
 }
 
 #[derive(Copy, Clone)]
-pub struct Rect {
-    pub min: Point,
-    pub max: Point,
+pub struct Vector2<T> {
+    pub e: [T; 2],
 }
 
-impl Rect {
-    pub fn new(min: Point, max: Point) -> Rect {
-        Rect { min, max }
+impl<T: Float> Vector2<T> {
+    pub fn new(x: T, y: T) -> Vector2<T> {
+        Vector2 { e: [x, y] }
     }
 }
 
 #[derive(Copy, Clone)]
-pub struct Vec3 {
-    pub e: [f32; 3],
+pub struct Vector3<T> {
+    pub e: [T; 3],
 }
 
-impl Vec3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
-        Vec3 { e: [x, y, z] }
+impl<T: Float> Vector3<T> {
+    pub fn new(x: T, y: T, z: T) -> Vector3<T> {
+        Vector3 { e: [x, y, z] }
     }
 
-    pub fn one() -> Vec3 {
-        Vec3 { e: [1.0, 1.0, 1.0] }
+    pub fn one() -> Vector3<T> {
+        Vector3 { e: [T::ONE; 3] }
     }
 
-    pub fn with_elements(e: [f32; 3]) -> Vec3 {
-        Vec3 { e }
+    pub fn with_elements(e: [T; 3]) -> Vector3<T> {
+        Vector3 { e }
     }
 
-    pub fn cross(&self, other: Vec3) -> Vec3 {
-        Vec3::new(
+    pub fn cross(&self, other: Vector3<T>) -> Vector3<T> {
+        Vector3::new(
             self.e[1] * other.e[2] - self.e[2] * other.e[1],
             self.e[2] * other.e[0] - self.e[0] * other.e[2],
             self.e[0] * other.e[1] - self.e[1] * other.e[0],
         )
     }
 
-    pub fn normalized(&self) -> Vec3 {
-        let len = self.len();
-        Vec3::new(self.e[0] / len, self.e[1] / len, self.e[2] / len)
+    // Reflects the incident vector about the normal `n`, like GLSL's `reflect`
+    pub fn reflect(&self, n: Vector3<T>) -> Vector3<T> {
+        *self - n * (T::TWO * (n * *self))
     }
 
-    pub fn len(&self) -> f32 {
-        (self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]).sqrt()
+    // Refracts the normalized incident vector through a surface with the normal `n`,
+    // `eta` is the ratio of the indices of refraction. Returns `None` on total internal
+    // reflection.
+    //
+    // See: https://registry.khronos.org/OpenGL-Refpages/gl4/html/refract.xhtml
+    pub fn refract(&self, n: Vector3<T>, eta: T) -> Option<Vector3<T>> {
+        let cos = n * *self;
+        let k = T::ONE - eta * eta * (T::ONE - cos * cos);
+        if k < T::ZERO {
+            return None;
+        }
+        Some(*self * eta - n * (eta * cos + k.sqrt()))
     }
 }
 
-impl Add for Vec3 {
-    type Output = Vec3;
+#[derive(Copy, Clone)]
+pub struct Vector4<T> {
+    pub e: [T; 4],
+}
+
+impl<T: Float> Vector4<T> {
+    pub fn new(x: T, y: T, z: T, w: T) -> Vector4<T> {
+        Vector4 { e: [x, y, z, w] }
+    }
 
-    fn add(self, rhs: Self) -> Self::Output {
-        Vec3::new(
-            self.e[0] + rhs.e[0],
-            self.e[1] + rhs.e[1],
-            self.e[2] + rhs.e[2],
-        )
+    pub fn from_vec3(vec3: Vector3<T>, w: T) -> Vector4<T> {
+        Vector4 {
+            e: [vec3.e[0], vec3.e[1], vec3.e[2], w],
+        }
     }
-}
 
-impl Sub for Vec3 {
-    type Output = Vec3;
+    pub fn xyz(&self) -> Vector3<T> {
+        Vector3::new(self.e[0], self.e[1], self.e[2])
+    }
+}
 
-    fn sub(self, rhs: Self) -> Self::Output {
-        Vec3::new(
-            self.e[0] - rhs.e[0],
-            self.e[1] - rhs.e[1],
-            self.e[2] - rhs.e[2],
+impl<T: Float> Vector4<T> {
+    pub fn perspective_division(&self) -> Vector3<T> {
+        Vector3::new(
+            self.e[0] / self.e[3],
+            self.e[1] / self.e[3],
+            self.e[2] / self.e[3],
         )
     }
 }
 
-impl Mul for Vec3 {
-    type Output = f32;
+// Operations shared by all vectors. Like GLSL, `a * b` of two vectors isn't defined as
+// component-wise, it's the dot product, use `mul_elements` for the former.
+macro_rules! impl_vector {
+    ($vec:ident, $n:expr) => {
+        impl<T: Float> $vec<T> {
+            pub fn zero() -> $vec<T> {
+                $vec { e: [T::ZERO; $n] }
+            }
 
-    fn mul(self, rhs: Self) -> Self::Output {
-        self.e[0] * rhs.e[0] + self.e[1] * rhs.e[1] + self.e[2] * rhs.e[2]
-        //        self.e.iter().zip(rhs.e.iter()).map(|(a, b)| a * b).sum()
-    }
+            pub fn splat(v: T) -> $vec<T> {
+                $vec { e: [v; $n] }
+            }
+
+            pub fn cast<U: Float>(&self) -> $vec<U> {
+                $vec {
+                    e: cast_array(self.e),
+                }
+            }
+
+            pub fn dot(&self, other: $vec<T>) -> T {
+                let mut sum = T::ZERO;
+                for i in 0..$n {
+                    sum += self.e[i] * other.e[i];
+                }
+                sum
+            }
+
+            pub fn len(&self) -> T {
+                self.dot(*self).sqrt()
+            }
+
+            pub fn normalized(&self) -> $vec<T> {
+                *self / self.len()
+            }
+
+            pub fn lerp(&self, other: $vec<T>, t: T) -> $vec<T> {
+                *self + (other - *self) * t
+            }
+
+            pub fn mul_elements(&self, other: $vec<T>) -> $vec<T> {
+                self.zip(other, |a, b| a * b)
+            }
+
+            pub fn min(&self, other: $vec<T>) -> $vec<T> {
+                self.zip(other, T::min)
+            }
+
+            pub fn max(&self, other: $vec<T>) -> $vec<T> {
+                self.zip(other, T::max)
+            }
+
+            pub fn map(&self, f: impl Fn(T) -> T) -> $vec<T> {
+                $vec { e: self.e.map(f) }
+            }
+
+            fn zip(&self, other: $vec<T>, f: impl Fn(T, T) -> T) -> $vec<T> {
+                let mut e = self.e;
+                for i in 0..$n {
+                    e[i] = f(e[i], other.e[i]);
+                }
+                $vec { e }
+            }
+        }
+
+        impl<T: Float> Add for $vec<T> {
+            type Output = $vec<T>;
+
+            fn add(self, rhs: Self) -> Self::Output {
+                self.zip(rhs, |a, b| a + b)
+            }
+        }
+
+        impl<T: Float> Sub for $vec<T> {
+            type Output = $vec<T>;
+
+            fn sub(self, rhs: Self) -> Self::Output {
+                self.zip(rhs, |a, b| a - b)
+            }
+        }
+
+        impl<T: Float> Mul for $vec<T> {
+            type Output = T;
+
+            fn mul(self, rhs: Self) -> Self::Output {
+                self.dot(rhs)
+            }
+        }
+
+        impl<T: Float> Mul<T> for $vec<T> {
+            type Output = $vec<T>;
+
+            fn mul(self, rhs: T) -> Self::Output {
+                self.map(|a| a * rhs)
+            }
+        }
+
+        impl Mul<$vec<f32>> for f32 {
+            type Output = $vec<f32>;
+
+            fn mul(self, rhs: $vec<f32>) -> Self::Output {
+                rhs * self
+            }
+        }
+
+        impl Mul<$vec<f64>> for f64 {
+            type Output = $vec<f64>;
+
+            fn mul(self, rhs: $vec<f64>) -> Self::Output {
+                rhs * self
+            }
+        }
+
+        impl<T: Float> Div<T> for $vec<T> {
+            type Output = $vec<T>;
+
+            fn div(self, rhs: T) -> Self::Output {
+                self.map(|a| a / rhs)
+            }
+        }
+
+        impl<T: Float> Neg for $vec<T> {
+            type Output = $vec<T>;
+
+            fn neg(self) -> Self::Output {
+                self.map(|a| -a)
+            }
+        }
+
+        impl<T: Float> AddAssign for $vec<T> {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl<T: Float> SubAssign for $vec<T> {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl<T: Float> MulAssign<T> for $vec<T> {
+            fn mul_assign(&mut self, rhs: T) {
+                *self = *self * rhs;
+            }
+        }
+
+        impl<T: Float> DivAssign<T> for $vec<T> {
+            fn div_assign(&mut self, rhs: T) {
+                *self = *self / rhs;
+            }
+        }
+
+        impl<T> Index<usize> for $vec<T> {
+            type Output = T;
+
+            fn index(&self, index: usize) -> &Self::Output {
+                &self.e[index]
+            }
+        }
+
+        impl<T> IndexMut<usize> for $vec<T> {
+            fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+                &mut self.e[index]
+            }
+        }
+    };
 }
 
-impl Mul<Vec3> for f32 {
-    type Output = Vec3;
+impl_vector!(Vector2, 2);
+impl_vector!(Vector3, 3);
+impl_vector!(Vector4, 4);
 
-    fn mul(self, rhs: Vec3) -> Self::Output {
-        Vec3::new(self * rhs.e[0], self * rhs.e[1], self * rhs.e[2])
-    }
+// Row-major 3x3 matrix, e.g. the upper-left part of a `Mat4` for transforming normals
+#[derive(Copy, Clone)]
+pub struct Matrix3<T> {
+    // Row-major order
+    pub e: [T; 9],
 }
 
-impl Neg for Vec3 {
-    type Output = Vec3;
+impl<T: Float> Matrix3<T> {
+    pub fn with_elements(e: [T; 9]) -> Matrix3<T> {
+        Matrix3 { e }
+    }
 
-    fn neg(self) -> Self::Output {
-        Vec3::new(-self.e[0], -self.e[1], -self.e[2])
+    pub fn identity() -> Matrix3<T> {
+        let mut e = [T::ZERO; 9];
+        e[0] = T::ONE;
+        e[4] = T::ONE;
+        e[8] = T::ONE;
+        Matrix3 { e }
     }
-}
 
-impl Div<f32> for Vec3 {
-    type Output = Vec3;
+    pub fn cast<U: Float>(&self) -> Matrix3<U> {
+        Matrix3::with_elements(cast_array(self.e))
+    }
 
-    fn div(self, rhs: f32) -> Self::Output {
-        Vec3::new(self.e[0] / rhs, self.e[1] / rhs, self.e[2] / rhs)
+    // Upper-left 3x3 part of `m`
+    pub fn from_mat4(m: &Matrix4<T>) -> Matrix3<T> {
+        Matrix3::with_elements([
+            m.e[0], m.e[1], m.e[2], m.e[4], m.e[5], m.e[6], m.e[8], m.e[9], m.e[10],
+        ])
     }
-}
 
-pub struct Vec4 {
-    pub e: [f32; 4],
-}
+    pub fn to_mat4(&self) -> Matrix4<T> {
+        let zero = T::ZERO;
+        Matrix4::with_elements([
+            self.e[0],
+            self.e[1],
+            self.e[2],
+            zero,
+            self.e[3],
+            self.e[4],
+            self.e[5],
+            zero,
+            self.e[6],
+            self.e[7],
+            self.e[8],
+            zero,
+            zero,
+            zero,
+            zero,
+            T::ONE,
+        ])
+    }
 
-impl Vec4 {
-    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Vec4 {
-        Vec4 { e: [x, y, z, w] }
+    pub fn row(&self, i: usize) -> Vector3<T> {
+        Vector3::new(self.e[i * 3], self.e[i * 3 + 1], self.e[i * 3 + 2])
     }
 
-    pub fn from_vec3(vec3: Vec3, w: f32) -> Vec4 {
-        Vec4 {
-            e: [vec3.e[0], vec3.e[1], vec3.e[2], w],
-        }
+    pub fn col(&self, i: usize) -> Vector3<T> {
+        Vector3::new(self.e[i], self.e[3 + i], self.e[6 + i])
     }
 
-    pub fn xyz(&self) -> Vec3 {
-        Vec3::new(self.e[0], self.e[1], self.e[2])
+    pub fn transpose(&self) -> Matrix3<T> {
+        Matrix3::with_elements([
+            self.e[0], self.e[3], self.e[6], self.e[1], self.e[4], self.e[7], self.e[2], self.e[5],
+            self.e[8],
+        ])
     }
-}
 
-impl Vec4 {
-    pub fn perspective_division(&self) -> Vec3 {
-        Vec3::new(
-            self.e[0] / self.e[3],
-            self.e[1] / self.e[3],
-            self.e[2] / self.e[3],
+    pub fn determinant(&self) -> T {
+        det3x3(
+            self.e[0], self.e[1], self.e[2], self.e[3], self.e[4], self.e[5], self.e[6], self.e[7],
+            self.e[8],
         )
     }
+
+    pub fn map(&self, f: impl Fn(T) -> T) -> Matrix3<T> {
+        Matrix3::with_elements(self.e.map(f))
+    }
+
+    // Returns `None` if the determinant is below `inverse_tolerance` relative to the
+    // product of the column lengths, its upper bound (Hadamard's inequality). Unlike the
+    // largest element cubed this doesn't reject anisotropic scales.
+    //
+    // See: https://en.wikipedia.org/wiki/Hadamard%27s_inequality
+    pub fn inverse(&self) -> Option<Matrix3<T>> {
+        let det = self.determinant();
+        let bound = self.col(0).len() * self.col(1).len() * self.col(2).len();
+        if det.abs() <= inverse_tolerance::<T>() * bound {
+            return None;
+        }
+
+        // The rows of the inverse are the cross products of the columns
+        let c0 = self.col(0);
+        let c1 = self.col(1);
+        let c2 = self.col(2);
+        let r0 = c1.cross(c2) / det;
+        let r1 = c2.cross(c0) / det;
+        let r2 = c0.cross(c1) / det;
+        Some(Matrix3::with_elements([
+            r0.e[0], r0.e[1], r0.e[2], r1.e[0], r1.e[1], r1.e[2], r2.e[0], r2.e[1], r2.e[2],
+        ]))
+    }
 }
 
-impl Mul<Vec4> for f32 {
-    type Output = Vec4;
+impl<T: Float> Mul for Matrix3<T> {
+    type Output = Matrix3<T>;
 
-    fn mul(self, rhs: Vec4) -> Self::Output {
-        Vec4::new(
-            self * rhs.e[0],
-            self * rhs.e[1],
-            self * rhs.e[2],
-            self * rhs.e[3],
-        )
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = Matrix3::with_elements([T::ZERO; 9]);
+        for i in 0..3 {
+            for j in 0..3 {
+                m.e[i * 3 + j] = self.row(i) * rhs.col(j);
+            }
+        }
+        m
+    }
+}
+
+impl<T: Float> Mul<Vector3<T>> for Matrix3<T> {
+    type Output = Vector3<T>;
+
+    fn mul(self, rhs: Vector3<T>) -> Self::Output {
+        Vector3::new(self.row(0) * rhs, self.row(1) * rhs, self.row(2) * rhs)
     }
 }
 
 #[derive(Copy, Clone)]
-pub struct Mat4 {
+pub struct Matrix4<T> {
     // Row-major order
-    pub e: [f32; 16],
+    pub e: [T; 16],
 }
 
-impl Mat4 {
-    pub fn with_elements(e: [f32; 16]) -> Mat4 {
-        Mat4 { e }
+impl<T: Float> Matrix4<T> {
+    pub fn with_elements(e: [T; 16]) -> Matrix4<T> {
+        Matrix4 { e }
     }
 
-    pub fn identity() -> Mat4 {
-        let mut e = [0.0; 16];
-        e[0] = 1.0;
-        e[5] = 1.0;
-        e[10] = 1.0;
-        e[15] = 1.0;
-        Mat4 { e }
+    pub fn identity() -> Matrix4<T> {
+        let mut e = [T::ZERO; 16];
+        e[0] = T::ONE;
+        e[5] = T::ONE;
+        e[10] = T::ONE;
+        e[15] = T::ONE;
+        Matrix4 { e }
     }
 
-    pub fn transpose(&self) -> Mat4 {
-        Mat4::with_elements([
+    // E.g. converts a `DMat4` built in double precision to a `Mat4` for rendering
+    pub fn cast<U: Float>(&self) -> Matrix4<U> {
+        Matrix4::with_elements(cast_array(self.e))
+    }
+
+    pub fn transpose(&self) -> Matrix4<T> {
+        Matrix4::with_elements([
             self.e[0], self.e[4], self.e[8], self.e[12], self.e[1], self.e[5], self.e[9],
             self.e[13], self.e[2], self.e[6], self.e[10], self.e[14], self.e[3], self.e[7],
             self.e[11], self.e[15],
         ])
     }
 
-    pub fn determinant(&self) -> f32 {
+    pub fn determinant(&self) -> T {
         self.e[0]
             * det3x3(
                 self.e[5], self.e[6], self.e[7], self.e[9], self.e[10], self.e[11], self.e[13],
@@ -218,8 +619,8 @@ impl Mat4 {
                 )
     }
 
-    pub fn adj(&self) -> Mat4 {
-        Mat4::with_elements([
+    pub fn adj(&self) -> Matrix4<T> {
+        Matrix4::with_elements([
             det3x3(
                 self.e[5], self.e[6], self.e[7], self.e[9], self.e[10], self.e[11], self.e[13],
                 self.e[14], self.e[15],
@@ -288,41 +689,135 @@ impl Mat4 {
         .transpose()
     }
 
-    pub fn inverse(&self) -> Option<Mat4> {
-        let det = self.determinant();
-        if det == 0.0 {
-            return None;
+    // Gauss-Jordan elimination with partial pivoting. Returns `None` if a pivot is
+    // smaller than `inverse_tolerance` relative to the largest element of its row, i.e.
+    // the matrix is singular or too close to it for the inverse to be meaningful. The
+    // translation column is left out of the row scales, a large translation doesn't make
+    // a matrix any closer to singular.
+    //
+    // See: https://en.wikipedia.org/wiki/Gaussian_elimination#Finding_the_inverse_of_a_matrix
+    pub fn inverse(&self) -> Option<Matrix4<T>> {
+        let mut a = self.e;
+        let mut inv = Matrix4::identity().e;
+
+        let mut scales = [T::ZERO; 4];
+        for (row, scale) in scales.iter_mut().enumerate() {
+            // The last row has no translation
+            let n = if row < 3 { 3 } else { 4 };
+            *scale = a[row * 4..row * 4 + n]
+                .iter()
+                .fold(T::ZERO, |m, e| m.max(e.abs()));
+        }
+
+        for col in 0..4 {
+            let mut pivot = col;
+            for row in col + 1..4 {
+                if a[row * 4 + col].abs() > a[pivot * 4 + col].abs() {
+                    pivot = row;
+                }
+            }
+            if a[pivot * 4 + col].abs() <= inverse_tolerance::<T>() * scales[pivot] {
+                return None;
+            }
+            if pivot != col {
+                for i in 0..4 {
+                    a.swap(pivot * 4 + i, col * 4 + i);
+                    inv.swap(pivot * 4 + i, col * 4 + i);
+                }
+                scales.swap(pivot, col);
+            }
+
+            let p = a[col * 4 + col];
+            for i in 0..4 {
+                a[col * 4 + i] /= p;
+                inv[col * 4 + i] /= p;
+            }
+
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let f = a[row * 4 + col];
+                if f == T::ZERO {
+                    continue;
+                }
+                for i in 0..4 {
+                    let a_col = a[col * 4 + i];
+                    let inv_col = inv[col * 4 + i];
+                    a[row * 4 + i] -= f * a_col;
+                    inv[row * 4 + i] -= f * inv_col;
+                }
+            }
         }
 
-        let adj = self.adj();
-        Some(adj / det)
+        Some(Matrix4::with_elements(inv))
+    }
+
+    // Inverse of an affine matrix (the last row is 0, 0, 0, 1), e.g. any composition of
+    // translations, rotations and scales. Only the upper-left 3x3 part is inverted.
+    pub fn inverse_affine(&self) -> Option<Matrix4<T>> {
+        debug_assert!(
+            self.e[12] == T::ZERO
+                && self.e[13] == T::ZERO
+                && self.e[14] == T::ZERO
+                && self.e[15] == T::ONE
+        );
+        let linear = Matrix3::from_mat4(self).inverse()?;
+        let t = linear * Vector3::new(self.e[3], self.e[7], self.e[11]);
+        let mut m = linear.to_mat4();
+        m.e[3] = -t.e[0];
+        m.e[7] = -t.e[1];
+        m.e[11] = -t.e[2];
+        Some(m)
+    }
+
+    // Transforms normals by the inverse transpose of the upper-left 3x3 part. This is the
+    // cofactor matrix, which only differs by the determinant as a factor, so normals have
+    // to be normalized after the transform but singular matrices still work.
+    //
+    // See: https://github.com/graphitemaster/normals_revisited
+    pub fn normal_matrix(&self) -> Matrix3<T> {
+        let m = Matrix3::from_mat4(self);
+        let c0 = m.col(0);
+        let c1 = m.col(1);
+        let c2 = m.col(2);
+        let n0 = c1.cross(c2);
+        let n1 = c2.cross(c0);
+        let n2 = c0.cross(c1);
+        // Keep the orientation of normals for mirroring transforms
+        let sign = if m.determinant() < T::ZERO {
+            -T::ONE
+        } else {
+            T::ONE
+        };
+        Matrix3::with_elements([
+            n0.e[0], n1.e[0], n2.e[0], n0.e[1], n1.e[1], n2.e[1], n0.e[2], n1.e[2], n2.e[2],
+        ])
+        .map(|e| e * sign)
     }
 }
 
-fn det3x3(
-    e00: f32,
-    e01: f32,
-    e02: f32,
-    e10: f32,
-    e11: f32,
-    e12: f32,
-    e20: f32,
-    e21: f32,
-    e22: f32,
-) -> f32 {
+// Pivots below this fraction of their row's scale, or determinants of a `Mat3` below this
+// fraction of their upper bound, are treated as 0
+fn inverse_tolerance<T: Float>() -> T {
+    T::EPSILON * T::from_f64(16.0)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn det3x3<T: Float>(e00: T, e01: T, e02: T, e10: T, e11: T, e12: T, e20: T, e21: T, e22: T) -> T {
     e00 * det2x2(e11, e12, e21, e22) - e01 * det2x2(e10, e12, e20, e22)
         + e02 * det2x2(e10, e11, e20, e21)
 }
 
-fn det2x2(e00: f32, e01: f32, e10: f32, e11: f32) -> f32 {
+fn det2x2<T: Float>(e00: T, e01: T, e10: T, e11: T) -> T {
     e00 * e11 - e01 * e10
 }
 
-impl Mul for Mat4 {
-    type Output = Mat4;
+impl<T: Float> Mul for Matrix4<T> {
+    type Output = Matrix4<T>;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        Mat4::with_elements([
+        Matrix4::with_elements([
             self.e[0] * rhs.e[0]
                 + self.e[1] * rhs.e[4]
                 + self.e[2] * rhs.e[8]
@@ -391,11 +886,11 @@ impl Mul for Mat4 {
     }
 }
 
-impl Mul<Vec4> for Mat4 {
-    type Output = Vec4;
+impl<T: Float> Mul<Vector4<T>> for Matrix4<T> {
+    type Output = Vector4<T>;
 
-    fn mul(self, rhs: Vec4) -> Self::Output {
-        Vec4::new(
+    fn mul(self, rhs: Vector4<T>) -> Self::Output {
+        Vector4::new(
             self.e[0] * rhs.e[0]
                 + self.e[1] * rhs.e[1]
                 + self.e[2] * rhs.e[2]
@@ -416,39 +911,47 @@ impl Mul<Vec4> for Mat4 {
     }
 }
 
-impl Div<f32> for Mat4 {
-    type Output = Mat4;
+impl<T: Float> Div<T> for Matrix4<T> {
+    type Output = Matrix4<T>;
 
-    fn div(self, rhs: f32) -> Self::Output {
-        let mut m = Mat4::with_elements([0.0; 16]);
-        for i in 0..16 {
-            m.e[i] = self.e[i] / rhs;
-        }
-        m
+    fn div(self, rhs: T) -> Self::Output {
+        Matrix4::with_elements(self.e.map(|e| e / rhs))
     }
 }
 
-impl Mat4 {
-    pub fn translate(t: Vec3) -> Mat4 {
-        let mut m = Mat4::identity();
+impl<T: Float> Matrix4<T> {
+    pub fn translate(t: Vector3<T>) -> Matrix4<T> {
+        let mut m = Matrix4::identity();
         m.e[3] = t.e[0];
         m.e[7] = t.e[1];
         m.e[11] = t.e[2];
         m
     }
 
-    pub fn scale(s: Vec3) -> Mat4 {
-        let mut m = Mat4::identity();
+    pub fn scale(s: Vector3<T>) -> Matrix4<T> {
+        let mut m = Matrix4::identity();
         m.e[0] = s.e[0];
         m.e[5] = s.e[1];
         m.e[10] = s.e[2];
         m
     }
 
-    pub fn rorate_y(rat: f32) -> Mat4 {
-        let mut m = Mat4::identity();
-        let sin = rat.sin();
-        let cos = rat.cos();
+    // Rotations are counter-clockwise in radians when looking against the axis
+    pub fn rotate_x(angle: T) -> Matrix4<T> {
+        let mut m = Matrix4::identity();
+        let sin = angle.sin();
+        let cos = angle.cos();
+        m.e[5] = cos;
+        m.e[6] = -sin;
+        m.e[9] = sin;
+        m.e[10] = cos;
+        m
+    }
+
+    pub fn rotate_y(angle: T) -> Matrix4<T> {
+        let mut m = Matrix4::identity();
+        let sin = angle.sin();
+        let cos = angle.cos();
         m.e[0] = cos;
         m.e[2] = sin;
         m.e[8] = -sin;
@@ -456,8 +959,53 @@ impl Mat4 {
         m
     }
 
-    pub fn from_basis(i: Vec3, j: Vec3, k: Vec3) -> Mat4 {
-        let mut m = Mat4::identity();
+    #[deprecated(note = "misspelled, use `rotate_y`")]
+    pub fn rorate_y(angle: T) -> Matrix4<T> {
+        Matrix4::rotate_y(angle)
+    }
+
+    pub fn rotate_z(angle: T) -> Matrix4<T> {
+        let mut m = Matrix4::identity();
+        let sin = angle.sin();
+        let cos = angle.cos();
+        m.e[0] = cos;
+        m.e[1] = -sin;
+        m.e[4] = sin;
+        m.e[5] = cos;
+        m
+    }
+
+    // Rodrigues' rotation formula
+    //
+    // See: https://en.wikipedia.org/wiki/Rotation_matrix#Rotation_matrix_from_axis_and_angle
+    pub fn rotate(axis: Vector3<T>, angle: T) -> Matrix4<T> {
+        let [x, y, z] = axis.normalized().e;
+        let sin = angle.sin();
+        let cos = angle.cos();
+        let t = T::ONE - cos;
+        let zero = T::ZERO;
+        Matrix4::with_elements([
+            t * x * x + cos,
+            t * x * y - sin * z,
+            t * x * z + sin * y,
+            zero,
+            t * x * y + sin * z,
+            t * y * y + cos,
+            t * y * z - sin * x,
+            zero,
+            t * x * z - sin * y,
+            t * y * z + sin * x,
+            t * z * z + cos,
+            zero,
+            zero,
+            zero,
+            zero,
+            T::ONE,
+        ])
+    }
+
+    pub fn from_basis(i: Vector3<T>, j: Vector3<T>, k: Vector3<T>) -> Matrix4<T> {
+        let mut m = Matrix4::identity();
         m.e[0] = i.e[0];
         m.e[4] = i.e[1];
         m.e[8] = i.e[2];
@@ -470,96 +1018,1390 @@ impl Mat4 {
         m
     }
 
-    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Mat4 {
+    pub fn look_at(eye: Vector3<T>, target: Vector3<T>, up: Vector3<T>) -> Matrix4<T> {
         let z = (eye - target).normalized();
         let x = up.cross(z).normalized();
         let y = z.cross(x).normalized();
-        Mat4::from_basis(x, y, z) * Mat4::translate(-eye)
+        // The camera basis is orthonormal, the rows of the view rotation are its axes
+        Matrix4::from_basis(x, y, z).transpose() * Matrix4::translate(-eye)
     }
 
-    pub fn frustum(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Mat4 {
-        Mat4::with_elements([
-            2.0 * near / (right - left),
-            0.0,
+    pub fn frustum(left: T, right: T, bottom: T, top: T, near: T, far: T) -> Matrix4<T> {
+        let zero = T::ZERO;
+        Matrix4::with_elements([
+            T::TWO * near / (right - left),
+            zero,
             (right + left) / (right - left),
-            0.0,
-            0.0,
-            2.0 * near / (top - bottom),
+            zero,
+            zero,
+            T::TWO * near / (top - bottom),
             (top + bottom) / (top - bottom),
-            0.0,
-            0.0,
-            0.0,
+            zero,
+            zero,
+            zero,
+            -(far + near) / (far - near),
+            -(T::TWO * far * near) / (far - near),
+            zero,
+            zero,
+            -T::ONE,
+            zero,
+        ])
+    }
+
+    // Parallel projection of the view space box between the `near` and `far` planes, which
+    // are distances along -z like in `frustum`, e.g. for directional light shadow maps
+    pub fn orthographic(left: T, right: T, bottom: T, top: T, near: T, far: T) -> Matrix4<T> {
+        let zero = T::ZERO;
+        Matrix4::with_elements([
+            T::TWO / (right - left),
+            zero,
+            zero,
+            -(right + left) / (right - left),
+            zero,
+            T::TWO / (top - bottom),
+            zero,
+            -(top + bottom) / (top - bottom),
+            zero,
+            zero,
+            -T::TWO / (far - near),
+            -(far + near) / (far - near),
+            zero,
+            zero,
+            zero,
+            T::ONE,
+        ])
+    }
+
+    // Symmetric `frustum` with the vertical field of view `fovy` in radians and `aspect`
+    // being width / height
+    pub fn perspective(fovy: T, aspect: T, near: T, far: T) -> Matrix4<T> {
+        let f = T::ONE / (fovy / T::TWO).tan();
+        let zero = T::ZERO;
+        Matrix4::with_elements([
+            f / aspect,
+            zero,
+            zero,
+            zero,
+            zero,
+            f,
+            zero,
+            zero,
+            zero,
+            zero,
             -(far + near) / (far - near),
-            -(2.0 * far * near) / (far - near),
-            0.0,
-            0.0,
-            -1.0,
-            0.0,
+            -(T::TWO * far * near) / (far - near),
+            zero,
+            zero,
+            -T::ONE,
+            zero,
         ])
     }
+
+    // `perspective` with the far plane at infinity, the limit as far goes to infinity
+    pub fn perspective_infinite(fovy: T, aspect: T, near: T) -> Matrix4<T> {
+        let mut m = Matrix4::perspective(fovy, aspect, near, T::ONE);
+        m.e[10] = -T::ONE;
+        m.e[11] = -T::TWO * near;
+        m
+    }
+
+    // `perspective` mapping the near plane to depth 1 and the far plane to depth 0 in a
+    // [0, 1] clip depth range like in D3D, use it with a `ClipDepth::ZeroToOne` viewport,
+    // a `Greater` depth test and a depth buffer cleared to 0. Floating point depth has the
+    // most precision close to 0 which balances the precision lost to the hyperbolic
+    // distribution of depth. Mapping to [-1, 1] first would lose that precision again.
+    //
+    // See: https://developer.nvidia.com/content/depth-precision-visualized
+    pub fn perspective_reversed_z(fovy: T, aspect: T, near: T, far: T) -> Matrix4<T> {
+        let mut m = Matrix4::perspective(fovy, aspect, near, far);
+        m.e[10] = near / (far - near);
+        m.e[11] = far * near / (far - near);
+        m
+    }
+
+    pub fn perspective_infinite_reversed_z(fovy: T, aspect: T, near: T) -> Matrix4<T> {
+        let mut m = Matrix4::perspective(fovy, aspect, near, T::ONE);
+        m.e[10] = T::ZERO;
+        m.e[11] = near;
+        m
+    }
+
+    // Transforms a position, including the perspective division
+    pub fn transform_point(&self, p: Vector3<T>) -> Vector3<T> {
+        (*self * Vector4::from_vec3(p, T::ONE)).perspective_division()
+    }
+
+    // Transforms a direction, ignoring the translation
+    pub fn transform_vector(&self, v: Vector3<T>) -> Vector3<T> {
+        (*self * Vector4::from_vec3(v, T::ZERO)).xyz()
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+// Unit quaternion representing a rotation, stored as [x, y, z, w] where w is the scalar
+// part
+//
+// See: https://www.euclideanspace.com/maths/geometry/rotations/conversions/
+#[derive(Copy, Clone)]
+pub struct Quaternion<T> {
+    pub e: [T; 4],
+}
 
-    fn assert_mat4_eq(m1: Mat4, m2: Mat4) {
-        for i in 0..16 {
-            assert!(m1.e[i] - m2.e[i] < 0.0001);
+impl<T: Float> Quaternion<T> {
+    pub fn new(x: T, y: T, z: T, w: T) -> Quaternion<T> {
+        Quaternion { e: [x, y, z, w] }
+    }
+
+    pub fn identity() -> Quaternion<T> {
+        Quaternion::new(T::ZERO, T::ZERO, T::ZERO, T::ONE)
+    }
+
+    pub fn cast<U: Float>(&self) -> Quaternion<U> {
+        Quaternion {
+            e: cast_array(self.e),
         }
     }
 
-    #[test]
-    fn test_mat4_transpose() {
-        assert_mat4_eq(
-            Mat4::with_elements([
-                1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0, 14.0, 15.0,
-                16.0,
-            ])
-            .transpose(),
-            Mat4::with_elements([
-                1.0, 5.0, 9.0, 13.0, 2.0, 6.0, 10.0, 14.0, 3.0, 7.0, 11.0, 15.0, 4.0, 8.0, 12.0,
-                16.0,
-            ]),
-        );
+    // Counter-clockwise rotation of `angle` radians around `axis` when looking against it
+    pub fn from_axis_angle(axis: Vector3<T>, angle: T) -> Quaternion<T> {
+        let axis = axis.normalized();
+        let sin = (angle / T::TWO).sin();
+        let cos = (angle / T::TWO).cos();
+        Quaternion::new(sin * axis.e[0], sin * axis.e[1], sin * axis.e[2], cos)
     }
 
-    #[test]
-    fn test_mat4_inverse() {
-        let m = Mat4::with_elements([
-            5.0, 0.0, 0.0, 0.0, 0.0, 0.0, 3.0, 0.0, 0.0, 1.0, 3.0, 0.0, 1.0, 0.0, 0.0, 1.0,
-        ]);
+    // Rotates around x first, then y, then z, i.e. `qz * qy * qx`
+    pub fn from_euler(x: T, y: T, z: T) -> Quaternion<T> {
+        let (zero, one) = (T::ZERO, T::ONE);
+        Quaternion::from_axis_angle(Vector3::new(zero, zero, one), z)
+            * Quaternion::from_axis_angle(Vector3::new(zero, one, zero), y)
+            * Quaternion::from_axis_angle(Vector3::new(one, zero, zero), x)
+    }
 
-        assert_eq!(m.determinant(), -15.0);
+    // Extracts the rotation of the upper-left 3x3 part of `m`, which must be a pure
+    // rotation
+    pub fn from_mat4(m: &Matrix4<T>) -> Quaternion<T> {
+        let e = &m.e;
+        let one = T::ONE;
+        let four = T::TWO * T::TWO;
+        let trace = e[0] + e[5] + e[10];
+        if trace > T::ZERO {
+            let s = (trace + one).sqrt() * T::TWO;
+            Quaternion::new(
+                (e[9] - e[6]) / s,
+                (e[2] - e[8]) / s,
+                (e[4] - e[1]) / s,
+                s / four,
+            )
+        } else if e[0] > e[5] && e[0] > e[10] {
+            let s = (one + e[0] - e[5] - e[10]).sqrt() * T::TWO;
+            Quaternion::new(
+                s / four,
+                (e[1] + e[4]) / s,
+                (e[2] + e[8]) / s,
+                (e[9] - e[6]) / s,
+            )
+        } else if e[5] > e[10] {
+            let s = (one + e[5] - e[0] - e[10]).sqrt() * T::TWO;
+            Quaternion::new(
+                (e[1] + e[4]) / s,
+                s / four,
+                (e[6] + e[9]) / s,
+                (e[2] - e[8]) / s,
+            )
+        } else {
+            let s = (one + e[10] - e[0] - e[5]).sqrt() * T::TWO;
+            Quaternion::new(
+                (e[2] + e[8]) / s,
+                (e[6] + e[9]) / s,
+                s / four,
+                (e[4] - e[1]) / s,
+            )
+        }
+    }
 
-        assert_mat4_eq(
-            m.adj(),
-            Mat4::with_elements([
-                -3.0, 0.0, 0.0, 0.0, 0.0, 15.0, -15.0, 0.0, 0.0, -5.0, 0.0, 0.0, 3.0, 0.0, 0.0,
-                -15.0,
-            ]),
-        );
+    pub fn to_mat4(&self) -> Matrix4<T> {
+        let [x, y, z, w] = self.e;
+        let (zero, one, two) = (T::ZERO, T::ONE, T::TWO);
+        Matrix4::with_elements([
+            one - two * (y * y + z * z),
+            two * (x * y - w * z),
+            two * (x * z + w * y),
+            zero,
+            two * (x * y + w * z),
+            one - two * (x * x + z * z),
+            two * (y * z - w * x),
+            zero,
+            two * (x * z - w * y),
+            two * (y * z + w * x),
+            one - two * (x * x + y * y),
+            zero,
+            zero,
+            zero,
+            zero,
+            one,
+        ])
+    }
 
-        assert_mat4_eq(
-            m.inverse().unwrap(),
-            Mat4::with_elements([
-                3.0 / 15.0,
-                0.0,
-                0.0,
-                0.0,
-                0.0,
-                -1.0,
-                1.0,
-                0.0,
-                0.0,
-                5.0 / 15.0,
-                0.0,
-                0.0,
-                -3.0 / 15.0,
-                0.0,
-                0.0,
-                1.0,
-            ]),
-        );
+    pub fn dot(&self, other: Quaternion<T>) -> T {
+        self.e[0] * other.e[0]
+            + self.e[1] * other.e[1]
+            + self.e[2] * other.e[2]
+            + self.e[3] * other.e[3]
+    }
+
+    pub fn len(&self) -> T {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalized(&self) -> Quaternion<T> {
+        let len = self.len();
+        Quaternion {
+            e: self.e.map(|e| e / len),
+        }
+    }
+
+    pub fn conjugate(&self) -> Quaternion<T> {
+        Quaternion::new(-self.e[0], -self.e[1], -self.e[2], self.e[3])
+    }
+
+    pub fn inverse(&self) -> Quaternion<T> {
+        let len2 = self.dot(*self);
+        Quaternion {
+            e: self.conjugate().e.map(|e| e / len2),
+        }
+    }
+
+    pub fn rotate(&self, v: Vector3<T>) -> Vector3<T> {
+        // v + 2w(q x v) + 2q x (q x v) with q the vector part
+        let q = Vector3::new(self.e[0], self.e[1], self.e[2]);
+        let t = q.cross(v) * T::TWO;
+        v + t * self.e[3] + q.cross(t)
+    }
+
+    // Normalized linear interpolation along the shorter arc, cheaper than `slerp` but
+    // doesn't have constant angular velocity
+    pub fn nlerp(&self, other: Quaternion<T>, t: T) -> Quaternion<T> {
+        let other = if self.dot(other) < T::ZERO {
+            -other
+        } else {
+            other
+        };
+        let mut e = [T::ZERO; 4];
+        for (i, e) in e.iter_mut().enumerate() {
+            *e = self.e[i] + t * (other.e[i] - self.e[i]);
+        }
+        Quaternion { e }.normalized()
+    }
+
+    // Spherical linear interpolation along the shorter arc
+    pub fn slerp(&self, other: Quaternion<T>, t: T) -> Quaternion<T> {
+        let mut cos = self.dot(other);
+        let other = if cos < T::ZERO {
+            cos = -cos;
+            -other
+        } else {
+            other
+        };
+        // Almost the same rotation, sin(theta) approaches 0
+        if cos > T::from_f64(0.9995) {
+            return self.nlerp(other, t);
+        }
+
+        let theta = cos.acos();
+        let sin = theta.sin();
+        let a = ((T::ONE - t) * theta).sin() / sin;
+        let b = (t * theta).sin() / sin;
+        let mut e = [T::ZERO; 4];
+        for (i, e) in e.iter_mut().enumerate() {
+            *e = a * self.e[i] + b * other.e[i];
+        }
+        Quaternion { e }
+    }
+}
+
+// Hamilton product, `a * b` rotates by `b` first and then by `a`
+impl<T: Float> Mul for Quaternion<T> {
+    type Output = Quaternion<T>;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let [x1, y1, z1, w1] = self.e;
+        let [x2, y2, z2, w2] = rhs.e;
+        Quaternion::new(
+            w1 * x2 + x1 * w2 + y1 * z2 - z1 * y2,
+            w1 * y2 - x1 * z2 + y1 * w2 + z1 * x2,
+            w1 * z2 + x1 * y2 - y1 * x2 + z1 * w2,
+            w1 * w2 - x1 * x2 - y1 * y2 - z1 * z2,
+        )
+    }
+}
+
+impl<T: Float> Neg for Quaternion<T> {
+    type Output = Quaternion<T>;
+
+    fn neg(self) -> Self::Output {
+        Quaternion {
+            e: self.e.map(|e| -e),
+        }
+    }
+}
+
+// Geometric primitives in world or view space, all in single precision
+
+#[derive(Copy, Clone)]
+pub struct Ray {
+    pub origin: Vec3,
+    // Not necessarily normalized, distances returned by the intersection tests are in
+    // units of `direction`
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Ray {
+        Ray { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + t * self.direction
+    }
+
+    // Möller–Trumbore, returns the distance along the ray and the barycentric coordinates
+    // of the hit for `b` and `c`. Both sides of the triangle are hit.
+    //
+    // See: https://www.graphics.cornell.edu/pubs/1997/MT97.pdf
+    pub fn intersect_triangle(&self, triangle: &Triangle) -> Option<(f32, f32, f32)> {
+        let e1 = triangle.b - triangle.a;
+        let e2 = triangle.c - triangle.a;
+        let p = self.direction.cross(e2);
+        let det = e1 * p;
+        // Parallel to the triangle's plane
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let s = self.origin - triangle.a;
+        let u = inv_det * (s * p);
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(e1);
+        let v = inv_det * (self.direction * q);
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = inv_det * (e2 * q);
+        if t < 0.0 {
+            return None;
+        }
+        Some((t, u, v))
+    }
+
+    // Slab test, returns the distances where the ray enters and leaves the box. The ray
+    // enters at 0.0 if its origin is inside.
+    //
+    // See: https://tavianator.com/2011/ray_box.html
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<(f32, f32)> {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::INFINITY;
+        for i in 0..3 {
+            // Division by 0 gives infinities which the min/max below handle, unless the
+            // origin lies exactly on the slab
+            let inv = 1.0 / self.direction.e[i];
+            let t0 = (aabb.min.e[i] - self.origin.e[i]) * inv;
+            let t1 = (aabb.max.e[i] - self.origin.e[i]) * inv;
+            if t0.is_nan() || t1.is_nan() {
+                continue;
+            }
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+        if t_min <= t_max {
+            Some((t_min, t_max))
+        } else {
+            None
+        }
+    }
+
+    // Nearest non-negative distance to the sphere's surface
+    pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f32> {
+        let oc = self.origin - sphere.center;
+        let a = self.direction * self.direction;
+        let half_b = oc * self.direction;
+        let c = oc * oc - sphere.radius * sphere.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrt = discriminant.sqrt();
+        let t0 = (-half_b - sqrt) / a;
+        let t1 = (-half_b + sqrt) / a;
+        if t0 >= 0.0 {
+            Some(t0)
+        } else if t1 >= 0.0 {
+            Some(t1)
+        } else {
+            None
+        }
+    }
+
+    pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+        let denom = plane.normal * self.direction;
+        if denom.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = -plane.signed_distance(self.origin) / denom;
+        if t >= 0.0 {
+            Some(t)
+        } else {
+            None
+        }
+    }
+}
+
+// Points p with `normal * p + d == 0`, the positive half-space is in front of the plane
+#[derive(Copy, Clone)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub d: f32,
+}
+
+impl Plane {
+    pub fn new(normal: Vec3, d: f32) -> Plane {
+        Plane { normal, d }
+    }
+
+    pub fn from_point_normal(p: Vec3, normal: Vec3) -> Plane {
+        let normal = normal.normalized();
+        Plane::new(normal, -(normal * p))
+    }
+
+    // Counter-clockwise points face the front
+    pub fn from_points(a: Vec3, b: Vec3, c: Vec3) -> Plane {
+        Plane::from_point_normal(a, (b - a).cross(c - a))
+    }
+
+    // Scales the equation so `signed_distance` is in world units, degenerate planes with a
+    // zero normal are left as is
+    pub fn normalized(&self) -> Plane {
+        let len = self.normal.len();
+        if len == 0.0 {
+            return *self;
+        }
+        Plane::new(self.normal / len, self.d / len)
+    }
+
+    pub fn signed_distance(&self, p: Vec3) -> f32 {
+        self.normal * p + self.d
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub fn new(center: Vec3, radius: f32) -> Sphere {
+        Sphere { center, radius }
+    }
+
+    // Sphere around the center of the points' bounding box, not the minimal one
+    pub fn from_points(points: impl IntoIterator<Item = Vec3> + Clone) -> Sphere {
+        let center = Aabb::from_points(points.clone()).center();
+        let radius = points
+            .into_iter()
+            .fold(0.0f32, |r, p| r.max((p - center).len()));
+        Sphere::new(center, radius)
+    }
+
+    pub fn contains_point(&self, p: Vec3) -> bool {
+        (p - self.center).len() <= self.radius
+    }
+}
+
+// Axis-aligned bounding box, empty if `min` is larger than `max` on any axis
+#[derive(Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn empty() -> Aabb {
+        Aabb::new(Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY))
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Aabb {
+        let mut aabb = Aabb::empty();
+        for p in points {
+            aabb.extend(p);
+        }
+        aabb
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.min.e[i] > self.max.e[i])
+    }
+
+    pub fn extend(&mut self, p: Vec3) {
+        self.min = self.min.min(p);
+        self.max = self.max.max(p);
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    // Half the size on each axis
+    pub fn extents(&self) -> Vec3 {
+        0.5 * (self.max - self.min)
+    }
+
+    pub fn contains_point(&self, p: Vec3) -> bool {
+        (0..3).all(|i| self.min.e[i] <= p.e[i] && p.e[i] <= self.max.e[i])
+    }
+
+    pub fn intersects_aabb(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min.e[i] <= other.max.e[i] && other.min.e[i] <= self.max.e[i])
+    }
+
+    // Bounding box of the transformed box for an affine `m`
+    //
+    // See: Arvo, "Transforming Axis-Aligned Bounding Boxes", Graphics Gems
+    pub fn transformed(&self, m: &Mat4) -> Aabb {
+        let center = m.transform_point(self.center());
+        let extents = self.extents();
+        let mut half = Vec3::zero();
+        for i in 0..3 {
+            for j in 0..3 {
+                half.e[i] += m.e[i * 4 + j].abs() * extents.e[j];
+            }
+        }
+        Aabb::new(center - half, center + half)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Triangle {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub c: Vec3,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3) -> Triangle {
+        Triangle { a, b, c }
+    }
+
+    // Counter-clockwise vertices face the front
+    pub fn normal(&self) -> Vec3 {
+        (self.b - self.a).cross(self.c - self.a).normalized()
+    }
+
+    // Barycentric coordinates of `p` projected onto the triangle's plane
+    //
+    // See: Ericson, "Real-Time Collision Detection", 3.4
+    pub fn barycentric(&self, p: Vec3) -> Vec3 {
+        let v0 = self.b - self.a;
+        let v1 = self.c - self.a;
+        let v2 = p - self.a;
+        let d00 = v0 * v0;
+        let d01 = v0 * v1;
+        let d11 = v1 * v1;
+        let d20 = v2 * v0;
+        let d21 = v2 * v1;
+        let denom = d00 * d11 - d01 * d01;
+        let v = (d11 * d20 - d01 * d21) / denom;
+        let w = (d00 * d21 - d01 * d20) / denom;
+        Vec3::new(1.0 - v - w, v, w)
+    }
+
+    // Whether `p`, which should lie in the triangle's plane, is inside the triangle or on
+    // its edges
+    pub fn contains_point(&self, p: Vec3) -> bool {
+        let b = self.barycentric(p);
+        b.e.iter().all(|c| *c >= 0.0)
+    }
+}
+
+// Six planes with normals pointing inwards, in the order left, right, bottom, top, near,
+// far
+#[derive(Copy, Clone)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // Extracts the planes of the clip volume -w <= x, y, z <= w from a (view) projection
+    // matrix. The planes are in the space the matrix transforms from, e.g. world space for
+    // a view-projection matrix. An infinite far plane always passes. For a [0, 1] clip
+    // depth range, e.g. `perspective_reversed_z`, the plane at depth 0 is too loose,
+    // which keeps culling conservative.
+    //
+    // See: Gribb, Hartmann, "Fast Extraction of Viewing Frustum Planes from the
+    // World-View-Projection Matrix"
+    pub fn from_mat4(m: &Mat4) -> Frustum {
+        let row = |i: usize| Vec4::new(m.e[i * 4], m.e[i * 4 + 1], m.e[i * 4 + 2], m.e[i * 4 + 3]);
+        let plane = |v: Vec4| Plane::new(v.xyz(), v.e[3]).normalized();
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [
+                plane(r3 + r0),
+                plane(r3 - r0),
+                plane(r3 + r1),
+                plane(r3 - r1),
+                plane(r3 + r2),
+                plane(r3 - r2),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, p: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(p) >= 0.0)
+    }
+
+    // Conservative, a sphere close to a corner outside of the frustum may still pass
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    // Conservative like `intersects_sphere`, tests the corner furthest along each plane's
+    // normal
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let mut p = aabb.min;
+            for i in 0..3 {
+                if plane.normal.e[i] >= 0.0 {
+                    p.e[i] = aabb.max.e[i];
+                }
+            }
+            plane.signed_distance(p) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::approx::*;
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    fn assert_mat4_eq(m1: Mat4, m2: Mat4) {
+        assert!(m1.approx_eq_with(&m2, 0.0001, DEFAULT_ULPS));
+    }
+
+    #[test]
+    fn test_mat4_transpose() {
+        assert_mat4_eq(
+            Mat4::with_elements([
+                1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0, 14.0, 15.0,
+                16.0,
+            ])
+            .transpose(),
+            Mat4::with_elements([
+                1.0, 5.0, 9.0, 13.0, 2.0, 6.0, 10.0, 14.0, 3.0, 7.0, 11.0, 15.0, 4.0, 8.0, 12.0,
+                16.0,
+            ]),
+        );
+    }
+
+    #[test]
+    fn test_mat4_inverse() {
+        let m = Mat4::with_elements([
+            5.0, 0.0, 0.0, 0.0, 0.0, 0.0, 3.0, 0.0, 0.0, 1.0, 3.0, 0.0, 1.0, 0.0, 0.0, 1.0,
+        ]);
+
+        assert_eq!(m.determinant(), -15.0);
+
+        assert_mat4_eq(
+            m.adj(),
+            Mat4::with_elements([
+                -3.0, 0.0, 0.0, 0.0, 0.0, 15.0, -15.0, 0.0, 0.0, -5.0, 0.0, 0.0, 3.0, 0.0, 0.0,
+                -15.0,
+            ]),
+        );
+
+        assert_mat4_eq(
+            m.inverse().unwrap(),
+            Mat4::with_elements([
+                3.0 / 15.0,
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+                -1.0,
+                1.0,
+                0.0,
+                0.0,
+                5.0 / 15.0,
+                0.0,
+                0.0,
+                -3.0 / 15.0,
+                0.0,
+                0.0,
+                1.0,
+            ]),
+        );
+    }
+
+    #[test]
+    fn test_mat4_inverse_singular() {
+        let m = Mat4::with_elements([
+            1.0, 2.0, 3.0, 4.0, 2.0, 4.0, 6.0, 8.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+        ]);
+        assert!(m.inverse().is_none());
+        // The second row is only off by rounding errors
+        let m = Mat4::with_elements([
+            1.0, 2.0, 3.0, 4.0, 2.0, 4.0, 6.0, 8.000001, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+        ]);
+        assert!(m.inverse().is_none());
+        assert!(Mat4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+        // Small but well-conditioned
+        let m = Mat4::rotate_x(0.5) / 1.0e6;
+        assert_mat4_eq(m.inverse().unwrap() * m, Mat4::identity());
+        // Large translations don't count towards the tolerance
+        let m = Mat4::translate(Vec3::new(1.0e6, 0.0, 0.0));
+        assert_mat4_eq(
+            m.inverse().unwrap(),
+            Mat4::translate(Vec3::new(-1.0e6, 0.0, 0.0)),
+        );
+        let m = Mat4::translate(Vec3::new(1.0e4, 0.0, 0.0)) * Mat4::scale(Vec3::splat(0.01));
+        let expected =
+            Mat4::scale(Vec3::splat(100.0)) * Mat4::translate(Vec3::new(-1.0e4, 0.0, 0.0));
+        assert!(m
+            .inverse()
+            .unwrap()
+            .approx_eq_with(&expected, 0.01, DEFAULT_ULPS));
+    }
+
+    #[test]
+    fn test_mat4_inverse_affine() {
+        let m = Mat4::translate(Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::rotate(Vec3::new(1.0, 1.0, 0.0), 0.7)
+            * Mat4::scale(Vec3::new(2.0, 0.5, 3.0));
+        assert_mat4_eq(m.inverse_affine().unwrap(), m.inverse().unwrap());
+        assert_mat4_eq(m.inverse_affine().unwrap() * m, Mat4::identity());
+        assert!(Mat4::scale(Vec3::new(0.0, 1.0, 1.0))
+            .inverse_affine()
+            .is_none());
+        // Anisotropic scales are as invertible as uniform ones
+        for s in [
+            Vec3::new(100.0, 0.01, 1.0),
+            Vec3::new(1.0e-4, 1.0e3, 1.0e-2),
+        ] {
+            let m = Mat4::translate(Vec3::new(1.0, 2.0, 3.0)) * Mat4::scale(s);
+            let inverse = m.inverse().unwrap();
+            assert!(m
+                .inverse_affine()
+                .unwrap()
+                .approx_eq_with(&inverse, 0.0001, DEFAULT_ULPS));
+        }
+    }
+
+    #[test]
+    fn test_mat4_normal_matrix() {
+        let m = Mat4::rotate_z(0.3) * Mat4::scale(Vec3::new(4.0, 1.0, 1.0));
+        // The surface x + y = 0 keeps its normal perpendicular to its tangents
+        let t = m.transform_vector(Vec3::new(1.0, -1.0, 0.0));
+        let n = m.normal_matrix() * Vec3::new(1.0, 1.0, 0.0);
+        assert!((t * n).abs() < 0.0001);
+        assert_vec3_eq(
+            (m.normal_matrix() * Vec3::new(0.0, 0.0, 1.0)).normalized(),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        // Mirroring keeps normals pointing outwards
+        let m = Mat4::scale(Vec3::new(-1.0, 1.0, 1.0));
+        assert_vec3_eq(
+            m.normal_matrix() * Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        );
+        // Flattening still has well-defined normals
+        let m = Mat4::scale(Vec3::new(1.0, 1.0, 0.0));
+        assert_vec3_eq(
+            m.normal_matrix() * Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+    }
+
+    fn assert_vec3_eq(a: Vec3, b: Vec3) {
+        assert!(a.approx_eq_with(&b, 0.0001, DEFAULT_ULPS));
+    }
+
+    #[test]
+    fn test_mat4_rotate() {
+        assert_mat4_eq(
+            Mat4::rotate_x(FRAC_PI_2),
+            Mat4::with_elements([
+                1.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+            ]),
+        );
+        assert_mat4_eq(
+            Mat4::rotate_y(FRAC_PI_2),
+            Mat4::with_elements([
+                0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+            ]),
+        );
+        assert_mat4_eq(
+            Mat4::rotate_z(FRAC_PI_2),
+            Mat4::with_elements([
+                0.0, -1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+            ]),
+        );
+        assert_mat4_eq(
+            Mat4::rotate(Vec3::new(0.0, 2.0, 0.0), 0.7),
+            Mat4::rotate_y(0.7),
+        );
+        // A third of a turn around the diagonal cycles the axes
+        assert_mat4_eq(
+            Mat4::rotate(Vec3::one(), 2.0 * PI / 3.0),
+            Mat4::with_elements([
+                0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+            ]),
+        );
+    }
+
+    #[test]
+    fn test_mat4_look_at() {
+        // Off-axis, so mixing up the rows and columns of the view rotation shows
+        let eye = Vec3::new(1.0, 2.0, 3.0);
+        let target = Vec3::new(0.0, 0.5, -1.0);
+        let m = Mat4::look_at(eye, target, Vec3::new(0.0, 1.0, 0.0));
+        assert_vec3_eq(m.transform_point(eye), Vec3::zero());
+        let distance = (target - eye).len();
+        assert_vec3_eq(m.transform_point(target), Vec3::new(0.0, 0.0, -distance));
+        // Up stays in the upper half of the view
+        assert!(m.transform_vector(Vec3::new(0.0, 1.0, 0.0)).e[1] > 0.0);
+    }
+
+    #[test]
+    fn test_mat4_perspective() {
+        let m = Mat4::perspective(FRAC_PI_2, 2.0, 1.0, 3.0);
+        assert_mat4_eq(
+            m,
+            Mat4::with_elements([
+                0.5, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -2.0, -3.0, 0.0, 0.0, -1.0, 0.0,
+            ]),
+        );
+        assert_mat4_eq(m, Mat4::frustum(-2.0, 2.0, -1.0, 1.0, 1.0, 3.0));
+        assert_vec3_eq(
+            m.transform_point(Vec3::new(0.0, 0.0, -1.0)),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_vec3_eq(
+            m.transform_point(Vec3::new(6.0, 3.0, -3.0)),
+            Vec3::new(1.0, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn test_mat4_perspective_infinite() {
+        let m = Mat4::perspective_infinite(FRAC_PI_2, 1.0, 0.5);
+        assert_mat4_eq(
+            m,
+            Mat4::with_elements([
+                1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, -1.0, 0.0, 0.0, -1.0, 0.0,
+            ]),
+        );
+        assert_vec3_eq(
+            m.transform_point(Vec3::new(0.0, 0.0, -0.5)),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        let far = m.transform_point(Vec3::new(0.0, 0.0, -1.0e6));
+        assert!(far.e[2] < 1.0 && far.e[2] > 0.999);
+    }
+
+    #[test]
+    fn test_mat4_perspective_reversed_z() {
+        let m = Mat4::perspective_reversed_z(FRAC_PI_2, 2.0, 1.0, 3.0);
+        assert_mat4_eq(
+            m,
+            Mat4::with_elements([
+                0.5, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.5, 1.5, 0.0, 0.0, -1.0, 0.0,
+            ]),
+        );
+        assert_vec3_eq(
+            m.transform_point(Vec3::new(0.0, 0.0, -1.0)),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert_vec3_eq(
+            m.transform_point(Vec3::new(0.0, 0.0, -3.0)),
+            Vec3::new(0.0, 0.0, 0.0),
+        );
+
+        let m = Mat4::perspective_infinite_reversed_z(FRAC_PI_2, 1.0, 0.5);
+        assert_vec3_eq(
+            m.transform_point(Vec3::new(0.0, 0.0, -0.5)),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let far = m.transform_point(Vec3::new(0.0, 0.0, -1.0e6));
+        assert!(far.e[2] > 0.0 && far.e[2] < 1.0e-6);
+    }
+
+    #[test]
+    fn test_mat4_orthographic() {
+        let m = Mat4::orthographic(-2.0, 2.0, -1.0, 3.0, 1.0, 3.0);
+        assert_mat4_eq(
+            m,
+            Mat4::with_elements([
+                0.5, 0.0, 0.0, 0.0, 0.0, 0.5, 0.0, -0.5, 0.0, 0.0, -1.0, -2.0, 0.0, 0.0, 0.0, 1.0,
+            ]),
+        );
+        assert_vec3_eq(
+            m.transform_point(Vec3::new(2.0, 3.0, -3.0)),
+            Vec3::new(1.0, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn test_mat4_transform() {
+        let m = Mat4::translate(Vec3::new(1.0, 2.0, 3.0)) * Mat4::rotate_z(FRAC_PI_2);
+        let v = Vec3::new(1.0, 0.0, 0.0);
+        assert_vec3_eq(m.transform_point(v), Vec3::new(1.0, 3.0, 3.0));
+        assert_vec3_eq(m.transform_vector(v), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_vector_ops() {
+        let a = Vec4::new(1.0, 2.0, 3.0, 4.0);
+        let b = Vec4::new(4.0, 3.0, 2.0, 1.0);
+        assert_eq!((a + b).e, [5.0; 4]);
+        assert_eq!((a - b).e, [-3.0, -1.0, 1.0, 3.0]);
+        assert_eq!(a * b, 20.0);
+        assert_eq!((a * 2.0).e, (2.0 * a).e);
+        assert_eq!(a.mul_elements(b).e, [4.0, 6.0, 6.0, 4.0]);
+        assert_eq!(a.min(b).e, [1.0, 2.0, 2.0, 1.0]);
+        assert_eq!(a.max(b).e, [4.0, 3.0, 3.0, 4.0]);
+        assert_eq!(a.lerp(b, 0.25).e, [1.75, 2.25, 2.75, 3.25]);
+
+        let mut v = Vec2::new(3.0, 4.0);
+        assert_eq!(v.len(), 5.0);
+        v += Vec2::new(1.0, 1.0);
+        v *= 2.0;
+        v -= Vec2::new(0.0, 2.0);
+        v /= 4.0;
+        assert_eq!(v.e, [2.0, 2.0]);
+        v[1] = 7.0;
+        assert_eq!(v[1], 7.0);
+        assert_eq!((-v).e, [-2.0, -7.0]);
+    }
+
+    #[test]
+    fn test_vec3_reflect_refract() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let i = Vec3::new(1.0, -1.0, 0.0).normalized();
+        assert_vec3_eq(i.reflect(n), Vec3::new(1.0, 1.0, 0.0).normalized());
+
+        // Same medium, the ray passes straight through
+        assert_vec3_eq(i.refract(n, 1.0).unwrap(), i);
+        // Snell's law, sin(t) = eta * sin(i)
+        let t = i.refract(n, 1.0 / 1.5).unwrap();
+        assert!((t.len() - 1.0).abs() < 0.0001);
+        assert!((t.e[0] - FRAC_PI_4.sin() / 1.5).abs() < 0.0001);
+        // Total internal reflection
+        assert!(i.refract(n, 1.5).is_none());
+    }
+
+    #[test]
+    fn test_mat3() {
+        let m = Mat3::from_mat4(&(Mat4::rotate_z(0.4) * Mat4::scale(Vec3::new(2.0, 3.0, 4.0))));
+        assert!((m.determinant() - 24.0).abs() < 0.0001);
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        assert_vec3_eq(m.inverse().unwrap() * (m * v), v);
+        assert_vec3_eq((m.inverse().unwrap() * m) * v, v);
+        assert_vec3_eq(m.transpose().row(1), m.col(1));
+        assert_vec3_eq((m.to_mat4() * Vec4::from_vec3(v, 1.0)).xyz(), m * v);
+        assert!(
+            Mat3::with_elements([1.0, 2.0, 3.0, 2.0, 4.0, 6.0, 0.0, 0.0, 1.0])
+                .inverse()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_double_precision() {
+        // Far away from the origin f32 can't tell the two points apart
+        let eye = DVec3::new(1.0e8, 0.0, 0.0);
+        let p = DVec3::new(1.0e8 + 0.25, 0.0, 0.0);
+        assert_eq!((p.cast::<f32>() - eye.cast::<f32>()).e[0], 0.0);
+
+        // Transform to view space in f64, then convert for rasterization
+        let view = DMat4::translate(-eye);
+        let model = DMat4::translate(p) * DMat4::rotate_z(std::f64::consts::FRAC_PI_2);
+        let model_view: Mat4 = (view * model).cast();
+        assert_vec3_eq(
+            model_view.transform_point(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.25, 1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn test_quat_axis_angle() {
+        let q = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), FRAC_PI_2);
+        assert_vec3_eq(
+            q.rotate(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        // Agrees with the matrix rotation
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        let q = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.3);
+        let m = Mat4::rotate_y(0.3);
+        assert_vec3_eq(q.rotate(v), (m * Vec4::from_vec3(v, 1.0)).xyz());
+        assert_vec3_eq(q.inverse().rotate(q.rotate(v)), v);
+    }
+
+    #[test]
+    fn test_quat_mul() {
+        let x = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), FRAC_PI_2);
+        let z = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        let v = Vec3::new(0.0, 1.0, 0.0);
+        // Rotates around x first: y -> z, then around z: z stays
+        assert_vec3_eq((z * x).rotate(v), Vec3::new(0.0, 0.0, 1.0));
+        assert_vec3_eq((x * z).rotate(v), Vec3::new(-1.0, 0.0, 0.0));
+        assert_vec3_eq(
+            Quat::from_euler(FRAC_PI_2, 0.0, FRAC_PI_2).rotate(v),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn test_quat_mat4() {
+        let v = Vec3::new(1.0, -2.0, 0.5);
+        // Covers every branch of `from_mat4`
+        for angle in [0.5, 3.0] {
+            for axis in [
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(1.0, 2.0, 3.0),
+            ] {
+                let q = Quat::from_axis_angle(axis, angle);
+                let m = q.to_mat4();
+                assert_vec3_eq(q.rotate(v), (m * Vec4::from_vec3(v, 1.0)).xyz());
+                let p = Quat::from_mat4(&m);
+                assert!((p.dot(q).abs() - 1.0).abs() < 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quat_slerp() {
+        let axis = Vec3::new(0.0, 0.0, 1.0);
+        let a = Quat::identity();
+        let b = Quat::from_axis_angle(axis, FRAC_PI_2);
+        let v = Vec3::new(1.0, 0.0, 0.0);
+        let half = FRAC_PI_4;
+        assert_vec3_eq(
+            a.slerp(b, 0.5).rotate(v),
+            Vec3::new(half.cos(), half.sin(), 0.0),
+        );
+        assert_vec3_eq(a.nlerp(b, 0.5).rotate(v), a.slerp(b, 0.5).rotate(v));
+        // Constant angular velocity
+        let quarter = FRAC_PI_2 / 4.0;
+        assert_vec3_eq(
+            a.slerp(b, 0.25).rotate(v),
+            Vec3::new(quarter.cos(), quarter.sin(), 0.0),
+        );
+        // Takes the shorter arc when the quaternions are in opposite hemispheres
+        assert_vec3_eq(a.slerp(-b, 0.5).rotate(v), a.slerp(b, 0.5).rotate(v));
+        assert!((a.slerp(-b, 0.3).len() - 1.0).abs() < 0.0001);
+    }
+
+    // Deterministic xorshift generator for the property tests
+    struct Rng(u64);
+
+    impl Rng {
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        // Uniform in [min, max)
+        fn range(&mut self, min: f32, max: f32) -> f32 {
+            let t = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+            min + t * (max - min)
+        }
+
+        fn vec3(&mut self, min: f32, max: f32) -> Vec3 {
+            Vec3::new(
+                self.range(min, max),
+                self.range(min, max),
+                self.range(min, max),
+            )
+        }
+
+        // Composition of translation, rotation and a scale which may mirror
+        fn affine(&mut self) -> Mat4 {
+            let mut scale = self.vec3(0.5, 2.0);
+            if self.next_u64().is_multiple_of(2) {
+                scale.e[0] = -scale.e[0];
+            }
+            Mat4::translate(self.vec3(-10.0, 10.0))
+                * Mat4::rotate(self.vec3(-1.0, 1.0), self.range(-PI, PI))
+                * Mat4::scale(scale)
+        }
+
+        // Strictly diagonally dominant, so it's invertible and well-conditioned
+        fn matrix(&mut self) -> Mat4 {
+            let mut m = Mat4::with_elements([0.0; 16]);
+            for i in 0..16 {
+                m.e[i] = self.range(-1.0, 1.0);
+            }
+            for i in 0..4 {
+                let sign = if m.e[i * 5] < 0.0 { -1.0 } else { 1.0 };
+                m.e[i * 5] += sign * 4.0;
+            }
+            m
+        }
+    }
+
+    const ITERATIONS: usize = 1000;
+
+    #[test]
+    fn test_property_inverse() {
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+        for _ in 0..ITERATIONS {
+            let m = rng.matrix();
+            let inv = m.inverse().unwrap();
+            assert!((inv * m).approx_eq_with(&Mat4::identity(), 1e-5, DEFAULT_ULPS));
+            assert!((m * inv).approx_eq_with(&Mat4::identity(), 1e-5, DEFAULT_ULPS));
+
+            let m = rng.affine();
+            let inv = m.inverse_affine().unwrap();
+            assert!((inv * m).approx_eq_with(&Mat4::identity(), 1e-4, DEFAULT_ULPS));
+            assert!(inv.approx_eq_with(&m.inverse().unwrap(), 1e-4, DEFAULT_ULPS));
+        }
+    }
+
+    #[test]
+    fn test_property_transpose() {
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        for _ in 0..ITERATIONS {
+            let a = rng.matrix();
+            let b = rng.matrix();
+            assert_eq!(a.transpose().transpose().e, a.e);
+            assert!((a * b)
+                .transpose()
+                .approx_eq(&(b.transpose() * a.transpose())));
+        }
+    }
+
+    #[test]
+    fn test_property_cross() {
+        let mut rng = Rng(0xda942042e4dd58b5);
+        for _ in 0..ITERATIONS {
+            let a = rng.vec3(-10.0, 10.0);
+            let b = rng.vec3(-10.0, 10.0);
+            let c = a.cross(b);
+            let tolerance = 1e-5 * a.len() * a.len() * b.len().max(a.len());
+            assert!((c * a).approx_eq_with(&0.0, tolerance, 0));
+            assert!((c * b).approx_eq_with(&0.0, tolerance, 0));
+            assert!(b.cross(a).approx_eq(&-c));
+        }
+    }
+
+    #[test]
+    fn test_property_look_at() {
+        let mut rng = Rng(0x853c49e6748fea9b);
+        for _ in 0..ITERATIONS {
+            let eye = rng.vec3(-10.0, 10.0);
+            let target = rng.vec3(-10.0, 10.0);
+            let up = rng.vec3(-1.0, 1.0);
+            let forward = target - eye;
+            // Degenerate when up is parallel to the view direction
+            if forward.len() < 0.1 || up.cross(forward.normalized()).len() < 0.1 {
+                continue;
+            }
+
+            let m = Mat4::look_at(eye, target, up);
+            let r = Mat3::from_mat4(&m);
+            let rrt = r * r.transpose();
+            assert!(rrt
+                .to_mat4()
+                .approx_eq_with(&Mat4::identity(), 1e-5, DEFAULT_ULPS));
+            assert!(r.determinant().approx_eq_with(&1.0, 1e-5, DEFAULT_ULPS));
+            assert!(m
+                .transform_point(eye)
+                .approx_eq_with(&Vec3::zero(), 1e-4, DEFAULT_ULPS));
+            let t = m.transform_point(target).normalized();
+            assert!(t.approx_eq_with(&Vec3::new(0.0, 0.0, -1.0), 1e-4, DEFAULT_ULPS));
+        }
+    }
+
+    #[test]
+    fn test_ray_triangle() {
+        let triangle = Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        let (t, u, v) = Ray::new(Vec3::new(0.25, 0.5, 2.0), down)
+            .intersect_triangle(&triangle)
+            .unwrap();
+        assert!(t.approx_eq(&2.0));
+        assert!(u.approx_eq(&0.25));
+        assert!(v.approx_eq(&0.5));
+        // Back side
+        assert!(Ray::new(Vec3::new(0.25, 0.25, -1.0), -down)
+            .intersect_triangle(&triangle)
+            .is_some());
+        // Outside, behind and parallel
+        assert!(Ray::new(Vec3::new(0.75, 0.75, 1.0), down)
+            .intersect_triangle(&triangle)
+            .is_none());
+        assert!(Ray::new(Vec3::new(0.25, 0.25, -1.0), down)
+            .intersect_triangle(&triangle)
+            .is_none());
+        assert!(
+            Ray::new(Vec3::new(-1.0, 0.25, 0.0), Vec3::new(1.0, 0.0, 0.0))
+                .intersect_triangle(&triangle)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_ray_aabb() {
+        let aabb = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::one());
+        let (t0, t1) = Ray::new(Vec3::new(-3.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0))
+            .intersect_aabb(&aabb)
+            .unwrap();
+        assert_eq!((t0, t1), (2.0, 4.0));
+        // From inside
+        let (t0, t1) = Ray::new(Vec3::zero(), Vec3::new(0.0, 2.0, 0.0))
+            .intersect_aabb(&aabb)
+            .unwrap();
+        assert_eq!((t0, t1), (0.0, 0.5));
+        assert!(
+            Ray::new(Vec3::new(-3.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0))
+                .intersect_aabb(&aabb)
+                .is_none()
+        );
+        assert!(Ray::new(Vec3::new(3.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))
+            .intersect_aabb(&aabb)
+            .is_none());
+        // Diagonal through a corner
+        assert!(
+            Ray::new(Vec3::new(2.0, 2.0, 2.0), Vec3::new(-1.0, -1.0, -1.0))
+                .intersect_aabb(&aabb)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_ray_sphere_plane() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0);
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        assert!(ray.intersect_sphere(&sphere).unwrap().approx_eq(&4.0));
+        assert!(
+            Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0))
+                .intersect_sphere(&sphere)
+                .unwrap()
+                .approx_eq(&1.0)
+        );
+        assert!(
+            Ray::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 0.0, -1.0))
+                .intersect_sphere(&sphere)
+                .is_none()
+        );
+
+        let plane = Plane::from_points(
+            Vec3::new(0.0, 0.0, -2.0),
+            Vec3::new(1.0, 0.0, -2.0),
+            Vec3::new(0.0, 1.0, -2.0),
+        );
+        assert!(plane.signed_distance(Vec3::zero()).approx_eq(&2.0));
+        assert!(ray.intersect_plane(&plane).unwrap().approx_eq(&2.0));
+        assert!(ray.at(2.0).approx_eq(&Vec3::new(0.0, 0.0, -2.0)));
+    }
+
+    #[test]
+    fn test_triangle_contains_point() {
+        let triangle = Triangle::new(
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(2.0, 0.0, 1.0),
+            Vec3::new(0.0, 2.0, 1.0),
+        );
+        assert!(triangle.normal().approx_eq(&Vec3::new(0.0, 0.0, 1.0)));
+        assert!(triangle
+            .barycentric(Vec3::new(1.0, 1.0, 1.0))
+            .approx_eq(&Vec3::new(0.0, 0.5, 0.5)));
+        assert!(triangle.contains_point(Vec3::new(0.5, 0.5, 1.0)));
+        assert!(triangle.contains_point(Vec3::new(1.0, 0.0, 1.0)));
+        assert!(!triangle.contains_point(Vec3::new(1.5, 1.5, 1.0)));
+        assert!(!triangle.contains_point(Vec3::new(-0.1, 0.5, 1.0)));
+    }
+
+    #[test]
+    fn test_aabb() {
+        let aabb = Aabb::from_points([Vec3::new(1.0, 2.0, 3.0), Vec3::new(-1.0, 0.0, 5.0)]);
+        assert!(aabb.min.approx_eq(&Vec3::new(-1.0, 0.0, 3.0)));
+        assert!(aabb.max.approx_eq(&Vec3::new(1.0, 2.0, 5.0)));
+        assert!(aabb.contains_point(Vec3::new(0.0, 1.0, 4.0)));
+        assert!(!aabb.contains_point(Vec3::new(0.0, 3.0, 4.0)));
+        assert!(Aabb::empty().is_empty());
+        assert!(!aabb.is_empty());
+        assert!(aabb.intersects_aabb(&Aabb::new(Vec3::one(), Vec3::new(3.0, 3.0, 3.0))));
+        assert!(!aabb.intersects_aabb(&Aabb::new(Vec3::zero(), Vec3::new(1.0, 1.0, 2.0))));
+
+        let unit = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::one());
+        let m = Mat4::translate(Vec3::new(5.0, 0.0, 0.0)) * Mat4::rotate_z(FRAC_PI_4);
+        let transformed = unit.transformed(&m);
+        let half = 2.0f32.sqrt();
+        assert!(transformed.max.approx_eq(&Vec3::new(5.0 + half, half, 1.0)));
+
+        let sphere = Sphere::from_points([Vec3::new(-1.0, 0.0, 0.0), Vec3::new(3.0, 0.0, 0.0)]);
+        assert!(sphere.center.approx_eq(&Vec3::new(1.0, 0.0, 0.0)));
+        assert!(sphere.radius.approx_eq(&2.0));
+        assert!(sphere.contains_point(Vec3::new(1.0, 1.9, 0.0)));
+    }
+
+    #[test]
+    fn test_frustum() {
+        let view_projection = Mat4::perspective(FRAC_PI_2, 1.0, 1.0, 10.0)
+            * Mat4::look_at(
+                Vec3::zero(),
+                Vec3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            );
+        let frustum = Frustum::from_mat4(&view_projection);
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -5.0)));
+        assert!(frustum.contains_point(Vec3::new(4.9, 0.0, -5.0)));
+        assert!(!frustum.contains_point(Vec3::new(5.1, 0.0, -5.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.5)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -11.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 5.0)));
+        // Planes are normalized
+        assert!(frustum.planes[4]
+            .signed_distance(Vec3::new(0.0, 0.0, -3.0))
+            .approx_eq(&2.0));
+
+        // Straddling the right plane
+        let sphere = Sphere::new(Vec3::new(5.5, 0.0, -5.0), 1.0);
+        assert!(frustum.intersects_sphere(&sphere));
+        assert!(!frustum.intersects_sphere(&Sphere::new(Vec3::new(0.0, 0.0, 3.0), 1.0)));
+
+        let aabb = Aabb::new(Vec3::new(4.5, -1.0, -6.0), Vec3::new(6.0, 1.0, -4.0));
+        assert!(frustum.intersects_aabb(&aabb));
+        let behind = Aabb::new(Vec3::new(-1.0, -1.0, 1.0), Vec3::one());
+        assert!(!frustum.intersects_aabb(&behind));
+
+        let infinite = Frustum::from_mat4(&Mat4::perspective_infinite(FRAC_PI_2, 1.0, 1.0));
+        assert!(infinite.contains_point(Vec3::new(0.0, 0.0, -1.0e6)));
     }
 }